@@ -2,20 +2,39 @@ use std::io;
 use std::fs;
 use std::net::ToSocketAddrs;
 use std::path::{PathBuf, Path};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
-use roa;
 use roa::preload::*;
 use roa::tls::{TlsListener, ServerConfig, NoClientAuth};
 use roa::tls::internal::pemfile::{certs, pkcs8_private_keys};
 
-use crate::PolyState;
+use crate::{PolyState, RequestConfig};
 use crate::middleware;
+use crate::path_policy::IDENTITY_DIR_NAME;
 
 // TODO: Remove roa dependency (use hyper directly, refactor middleware fns)
-pub struct App {}
+#[derive(Default)]
+pub struct App {
+    dev_mode: bool,
+    strict_config: bool,
+}
 
 impl App {
+    /// Responds to server errors with diagnostic pages instead of bare statuses.
+    pub fn dev_mode(mut self, dev_mode: bool) -> Self {
+        self.dev_mode = dev_mode;
+
+        self
+    }
+
+    /// Refuses to start if any `.config.toml` under the web root is invalid.
+    pub fn strict_config(mut self, strict_config: bool) -> Self {
+        self.strict_config = strict_config;
+
+        self
+    }
+
     pub async fn listen(
         &self,
         interface: impl ToSocketAddrs,
@@ -29,9 +48,19 @@ impl App {
         
         let root = PathBuf::from(root_path).canonicalize()?;
 
+        if self.strict_config {
+            let errors = RequestConfig::validate_tree(root.as_path());
+
+            errors.iter().for_each(|err| log::error!("{}", err));
+
+            if !errors.is_empty() {
+                return Err(anyhow!("Found {} configuration error(s) under {:?}", errors.len(), root));
+            }
+        }
+
         // TODO: Config for Cert path, default "./identity/server.crt"
         // TODO: Config for Key path, default "./identity/server.key"
-        let id_path = root.join(Path::new(".identity"));
+        let id_path = root.join(IDENTITY_DIR_NAME);
         let cert_path = id_path.join("server.crt");
         let key_path = id_path.join("server.key");
 
@@ -47,7 +76,7 @@ impl App {
                 // TODO: Config flag for RSA or PKCS8 key file, default PKCS8
                 let keys = pkcs8_private_keys(&mut key_file);
                 match (cert_chain, keys) {
-                    (Ok(cert_chain), Ok(mut keys)) if keys.len() > 0 => {
+                    (Ok(cert_chain), Ok(mut keys)) if !keys.is_empty() => {
                         config.set_single_cert(cert_chain, keys.remove(0))?;
 
                         Some(config)
@@ -62,6 +91,11 @@ impl App {
         let state = PolyState {
             addr,
             root,
+            tls: config.is_some(),
+            dev_mode: self.dev_mode,
+            reported_errors: Arc::new(Mutex::new(Default::default())),
+            config_cache: Default::default(),
+            templates: Default::default(),
         };
 
         let app = roa::App::state(state)
@@ -69,11 +103,15 @@ impl App {
             .gate(middleware::logger)
             .gate(middleware::early_return)
             .gate(middleware::server_header)
+            .gate(middleware::error_page)
+            .gate(middleware::conditional_get)
             .gate(middleware::resolve_resource)
+            .gate(middleware::compress)
             .gate(middleware::allow_methods)
             .gate(middleware::trailing_slash)
             .gate(middleware::serve_file)
             .gate(middleware::render_hbs)
+            .gate(middleware::render_markdown)
             .gate(middleware::use_index)
             .gate(middleware::resolve_file)
             .gate(middleware::auto_index)
@@ -95,9 +133,3 @@ impl App {
         Ok(())
     }
 }
-
-impl Default for App {
-    fn default() -> Self {
-        App {}
-    }
-}
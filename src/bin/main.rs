@@ -12,7 +12,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     env_logger::init();
 
-    let app = App::default();
+    let app = App::default()
+        .dev_mode(opts.dev)
+        .strict_config(opts.strict);
 
     app.listen(
         (opts.interface, opts.port),
@@ -31,6 +33,18 @@ struct Opts {
         help = "Only bind over IPv4.",
     )]
     ipv4: bool,
+
+    #[clap(
+        long,
+        help = "Show diagnostic pages for server errors.",
+    )]
+    dev: bool,
+
+    #[clap(
+        long,
+        help = "Exit at startup if any .config.toml under the web root is invalid.",
+    )]
+    strict: bool,
     
     // TODO: pnet crate, allow bind to literal interface by name
     #[clap(
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{FileStamp, RequestConfig};
+use crate::request_config::{ConfigError, CONFIG_FILE_NAME};
+
+type CascadeResult = Result<Arc<RequestConfig>, Vec<ConfigError>>;
+
+/// Cascaded configuration per directory, shared between requests.
+///
+/// Entries remember the size and modification time of every `.config.toml`
+/// along the cascade, and are regenerated as soon as any of them changes,
+/// appears or disappears.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigCache {
+    entries: Arc<RwLock<HashMap<PathBuf, CacheEntry>>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    stamps: Vec<FileStamp>,
+    config: CascadeResult,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hits={}; misses={}; entries={}", self.hits, self.misses, self.entries)
+    }
+}
+
+impl ConfigCache {
+    /// Returns the cascaded configuration for `ancestors`, ordered deepest first.
+    pub fn get(&self, ancestors: &[&Path]) -> CascadeResult {
+        // Directories that don't exist can't hold a config file either
+        let dirs: Vec<&Path> = ancestors
+            .iter()
+            .copied()
+            .skip_while(|path| !path.is_dir())
+            .collect();
+
+        let key = match dirs.first() {
+            Some(&dir) => dir.to_owned(),
+            None => return RequestConfig::generate_from_ancestors(&dirs).map(Arc::new),
+        };
+
+        let stamps: Vec<FileStamp> = dirs
+            .iter()
+            .map(|dir| FileStamp::of(dir.join(CONFIG_FILE_NAME).as_path()))
+            .collect();
+
+        if let Ok(entries) = self.entries.read() {
+            if let Some(entry) = entries.get(&key).filter(|entry| entry.stamps == stamps) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+
+                return entry.config.clone();
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let config = RequestConfig::generate_from_ancestors(&dirs).map(Arc::new);
+
+        if let Ok(mut entries) = self.entries.write() {
+            entries.insert(key, CacheEntry { stamps, config: config.clone() });
+        }
+
+        config
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.read().map(|entries| entries.len()).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn invalidation_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+
+        let cache = ConfigCache::default();
+        let ancestors = [sub.as_path(), root.as_path()];
+
+        assert!(cache.get(&ancestors).unwrap().server.auto_index);
+        assert!(cache.get(&ancestors).unwrap().server.auto_index);
+        assert_eq!(CacheStats { hits: 1, misses: 1, entries: 1 }, cache.stats());
+
+        // A new file anywhere along the cascade invalidates the entry
+        fs::write(root.join(CONFIG_FILE_NAME), "[server]\nauto_index = false\n").unwrap();
+        assert!(!cache.get(&ancestors).unwrap().server.auto_index);
+        assert_eq!(2, cache.stats().misses);
+
+        // Requests for missing paths share the entry of the nearest existing directory
+        let missing = sub.join("missing.txt");
+        assert!(cache.get(&[missing.as_path(), sub.as_path(), root.as_path()]).is_ok());
+        assert_eq!(CacheStats { hits: 2, misses: 2, entries: 1 }, cache.stats());
+
+        // The nearest file wins
+        fs::write(sub.join(CONFIG_FILE_NAME), "[server]\nauto_index = true\n").unwrap();
+        assert!(cache.get(&ancestors).unwrap().server.auto_index);
+
+        // Size changes are caught even when the mtime is too coarse to tell
+        fs::write(sub.join(CONFIG_FILE_NAME), "[server]\nauto_index = \"no\"\n").unwrap();
+        assert!(cache.get(&ancestors).is_err());
+    }
+}
\ No newline at end of file
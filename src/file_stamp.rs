@@ -0,0 +1,31 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Size and modification time of a file, or nothing if it doesn't exist, to
+/// tell when data derived from it is stale without reading it again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileStamp {
+    path: PathBuf,
+    meta: Option<(SystemTime, u64)>,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Self {
+        let meta = path
+            .metadata()
+            .ok()
+            .filter(|meta| meta.is_file())
+            .and_then(|meta| Some((meta.modified().ok()?, meta.len())));
+
+        Self { path: path.to_owned(), meta }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.meta.is_some()
+    }
+
+    /// Whether the file is still as it was when stamped.
+    pub fn is_current(&self) -> bool {
+        *self == Self::of(self.path.as_path())
+    }
+}
\ No newline at end of file
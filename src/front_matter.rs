@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::{Map, Value as JsonValue};
+
+/// Front matter is only looked for within this many bytes of the start of a file.
+const MAX_FRONT_MATTER_LEN: u64 = 64 * 1024;
+
+/// Enough of a file to hold an opening fence, with a BOM and trailing whitespace.
+const MAX_FENCE_LINE_LEN: u64 = 16;
+
+/// Extensions of files that may start with front matter.
+const FRONT_MATTER_EXTENSIONS: &[&str] = &["hbs", "md", "markdown", "html", "htm"];
+
+/// Splits a YAML (`---`) or TOML (`+++`) front matter block off the start of
+/// `source`, returning its data and length in bytes, including the fences.
+///
+/// A block that fails to parse is still stripped, but has no data.
+pub fn split_front_matter(source: &str) -> Option<(JsonValue, usize)> {
+    let bom_len = match source.starts_with('\u{feff}') {
+        true => '\u{feff}'.len_utf8(),
+        false => 0,
+    };
+
+    let mut lines = source[bom_len..].split_inclusive('\n');
+
+    let opening = lines.next()?;
+    let fence = opening.trim_end();
+
+    if fence != "---" && fence != "+++" {
+        return None;
+    }
+
+    let start = bom_len + opening.len();
+    let mut end = start;
+
+    for line in lines {
+        if line.trim_end() == fence {
+            let data = match parse_block(fence, &source[start..end]) {
+                Ok(JsonValue::Null) => JsonValue::Object(Map::new()),
+                Ok(data @ JsonValue::Object(_)) => data,
+                Ok(_) => {
+                    log::warn!("Ignoring front matter that is not a table");
+
+                    JsonValue::Null
+                },
+                Err(err) => {
+                    log::warn!("Ignoring invalid front matter: {}", err);
+
+                    JsonValue::Null
+                },
+            };
+
+            return Some((data, end + line.len()));
+        }
+
+        end += line.len();
+    }
+
+    // An unclosed fence is just content, e.g. a thematic break in markdown
+    None
+}
+
+fn parse_block(fence: &str, data: &str) -> Result<JsonValue, String> {
+    match fence {
+        "---" => serde_yaml::from_str::<JsonValue>(data).map_err(|err| err.to_string()),
+        _ => toml::from_str::<toml::Value>(data).map(toml_to_json).map_err(|err| err.to_string()),
+    }
+}
+
+/// Reads the front matter of a file, if its extension supports it.
+pub fn read_front_matter(path: &Path) -> Option<(JsonValue, usize)> {
+    let supported = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| FRONT_MATTER_EXTENSIONS.contains(&ext));
+
+    if !supported {
+        return None;
+    }
+
+    let mut file = File::open(path).ok()?;
+    let mut buf = Vec::new();
+
+    // Most files have no front matter, so only read on past an opening fence
+    file.by_ref().take(MAX_FENCE_LINE_LEN).read_to_end(&mut buf).ok()?;
+
+    if !starts_with_fence(&buf) {
+        return None;
+    }
+
+    file.take(MAX_FRONT_MATTER_LEN - buf.len() as u64).read_to_end(&mut buf).ok()?;
+
+    // The prefix may end in the middle of a character
+    let source = match std::str::from_utf8(&buf) {
+        Ok(source) => source,
+        Err(err) => std::str::from_utf8(&buf[..err.valid_up_to()]).ok()?,
+    };
+
+    split_front_matter(source)
+}
+
+/// Whether the first line of `prefix` is a complete `---` or `+++` fence.
+fn starts_with_fence(prefix: &[u8]) -> bool {
+    let prefix = prefix.strip_prefix("\u{feff}".as_bytes()).unwrap_or(prefix);
+
+    match prefix.iter().position(|&byte| byte == b'\n') {
+        Some(end) => matches!(prefix[..end].trim_ascii_end(), b"---" | b"+++"),
+        None => false,
+    }
+}
+
+pub fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(value) => JsonValue::String(value),
+        toml::Value::Integer(value) => JsonValue::from(value),
+        toml::Value::Float(value) => JsonValue::from(value),
+        toml::Value::Boolean(value) => JsonValue::Bool(value),
+        toml::Value::Datetime(value) => JsonValue::String(value.to_string()),
+        toml::Value::Array(values) => JsonValue::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => JsonValue::Object(table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn yaml_front_matter_test() {
+        let source = "---\ntitle: Hello\ntags: [a, b]\n---\n# Body\n";
+        let (data, len) = split_front_matter(source).unwrap();
+
+        assert_eq!(json!({ "title": "Hello", "tags": ["a", "b"] }), data);
+        assert_eq!("# Body\n", &source[len..]);
+    }
+
+    #[test]
+    fn toml_front_matter_test() {
+        let source = "\u{feff}+++\r\ntitle = \"Hello\"\r\ndate = 2022-01-02\r\n+++\r\nbody";
+        let (data, len) = split_front_matter(source).unwrap();
+
+        assert_eq!(json!({ "title": "Hello", "date": "2022-01-02" }), data);
+        assert_eq!("body", &source[len..]);
+    }
+
+    #[test]
+    fn read_front_matter_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.md");
+
+        fs::write(&path, "\u{feff}---  \r\ntitle: Hello\n---\nBody").unwrap();
+        assert_eq!(Some(json!({ "title": "Hello" })), read_front_matter(&path).map(|(data, _)| data));
+
+        fs::write(&path, "# No front matter\n---\ntitle: Hello\n---\n").unwrap();
+        assert_eq!(None, read_front_matter(&path));
+
+        fs::write(&path, "---").unwrap();
+        assert_eq!(None, read_front_matter(&path));
+    }
+
+    #[test]
+    fn no_front_matter_test() {
+        assert_eq!(None, split_front_matter("# Title\n---\n"));
+        assert_eq!(None, split_front_matter("---\nunclosed: true\n"));
+        assert_eq!(None, split_front_matter(""));
+
+        let (data, len) = split_front_matter("---\n---\n").unwrap();
+        assert_eq!(json!({}), data);
+        assert_eq!(8, len);
+
+        // Invalid front matter is still stripped
+        let (data, _) = split_front_matter("+++\nnot toml\n+++\n").unwrap();
+        assert_eq!(JsonValue::Null, data);
+    }
+}
\ No newline at end of file
@@ -4,10 +4,19 @@ mod app;
 mod poly_state;
 mod middleware;
 mod request_config;
+mod config_cache;
+mod file_stamp;
+mod front_matter;
+mod site_data;
+mod listing;
+mod path_policy;
 mod resource;
 
 pub use app::App;
 
 use poly_state::PolyState;
 use request_config::{RequestConfig, ServerConfig};
+use config_cache::ConfigCache;
+use file_stamp::FileStamp;
+use path_policy::PathPolicy;
 use resource::Resource;
@@ -0,0 +1,421 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Serialize;
+
+use crate::resource::ResourceContext;
+
+/// Column a directory listing is sorted by, as in Apache's `?C=` parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortColumn {
+    Name,
+    Modified,
+    Size,
+    Type,
+}
+
+impl SortColumn {
+    pub const ALL: [SortColumn; 4] = [SortColumn::Name, SortColumn::Modified, SortColumn::Size, SortColumn::Type];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            SortColumn::Name => "N",
+            SortColumn::Modified => "M",
+            SortColumn::Size => "S",
+            SortColumn::Type => "T",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortColumn::Name => "name",
+            SortColumn::Modified => "modified",
+            SortColumn::Size => "size",
+            SortColumn::Type => "type",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|column| column.code().eq_ignore_ascii_case(code))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SortOrder::Ascending => "A",
+            SortOrder::Descending => "D",
+        }
+    }
+
+    fn reverse(&self) -> Self {
+        match self {
+            SortOrder::Ascending => SortOrder::Descending,
+            SortOrder::Descending => SortOrder::Ascending,
+        }
+    }
+}
+
+/// How to sort and filter a directory listing, from query parameters like
+/// Apache's: `C` (column), `O` (order) and `P` (a glob matched against names).
+/// A requested `format` is kept in the sort links.
+///
+/// Only the entries within `max_listing_entries` are sorted, but the filter is
+/// applied before the cap by `Resource::filtered_context`.
+#[derive(Debug)]
+pub struct ListingQuery {
+    column: SortColumn,
+    order: SortOrder,
+    dirs_first: bool,
+    filter: Option<String>,
+    matcher: Option<GlobMatcher>,
+    format: Option<String>,
+}
+
+impl ListingQuery {
+    /// Reads the listing parameters from a parsed query. Unknown values fall
+    /// back to sorting by name, ascending.
+    pub fn from_query(query: &BTreeMap<String, String>, dirs_first: bool) -> Self {
+        let column = query.get("C")
+            .and_then(|code| SortColumn::from_code(code))
+            .unwrap_or(SortColumn::Name);
+
+        let order = match query.get("O").map(String::as_str) {
+            Some("D") | Some("d") => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        };
+
+        let filter = query.get("P")
+            .map(|pattern| pattern.trim().to_owned())
+            .filter(|pattern| !pattern.is_empty());
+
+        let matcher = filter.as_ref().and_then(|pattern| {
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build();
+
+            match glob {
+                Ok(glob) => Some(glob.compile_matcher()),
+                Err(err) => {
+                    log::debug!("Ignoring invalid listing filter {:?}: {}", pattern, err);
+
+                    None
+                },
+            }
+        });
+
+        let format = query.get("format")
+            .map(|format| format.trim().to_owned())
+            .filter(|format| !format.is_empty());
+
+        Self { column, order, dirs_first, filter, matcher, format }
+    }
+
+    /// The name filter, if a valid one was given.
+    pub fn matcher(&self) -> Option<&GlobMatcher> {
+        self.matcher.as_ref()
+    }
+
+    /// Filters and sorts the children of `context` in place.
+    pub fn apply(&self, context: &mut ResourceContext) {
+        if let Some(children) = context.children_mut() {
+            if let Some(ref matcher) = self.matcher {
+                children.retain(|child| child.name().is_some_and(|name| matcher.is_match(name)));
+            }
+
+            children.sort_by(|a, b| self.compare(a, b));
+        }
+    }
+
+    fn compare(&self, a: &ResourceContext, b: &ResourceContext) -> Ordering {
+        let is_dir = |child: &ResourceContext| child.metadata().is_some_and(|meta| meta.is_dir());
+
+        let ordering = match self.column {
+            SortColumn::Name => Ordering::Equal,
+            SortColumn::Modified => modified(a).cmp(&modified(b)),
+            SortColumn::Size => size(a).cmp(&size(b)),
+            SortColumn::Type => extension(a).cmp(&extension(b)),
+        }
+        .then_with(|| sort_name(a).cmp(&sort_name(b)))
+        .then_with(|| a.name().cmp(&b.name()));
+
+        let ordering = match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        };
+
+        match self.dirs_first {
+            true => is_dir(b).cmp(&is_dir(a)).then(ordering),
+            false => ordering,
+        }
+    }
+
+    /// The sort state for templates, including a link for each column header,
+    /// which reverses the order of the current column.
+    pub fn state(&self) -> ListingState {
+        // Parameters every sort link carries over
+        let mut params = match self.filter {
+            Some(ref filter) => format!(";P={}", urlencoding::encode(filter)),
+            None => String::new(),
+        };
+
+        if let Some(ref format) = self.format {
+            params.push_str(format!(";format={}", urlencoding::encode(format)).as_str());
+        }
+
+        let sort_links = SortColumn::ALL
+            .iter()
+            .map(|&column| {
+                let order = match column == self.column {
+                    true => self.order.reverse(),
+                    false => SortOrder::Ascending,
+                };
+
+                (column.as_str(), format!("?C={};O={}{}", column.code(), order.code(), params))
+            })
+            .collect();
+
+        ListingState {
+            column: self.column.as_str(),
+            order: match self.order {
+                SortOrder::Ascending => "ascending",
+                SortOrder::Descending => "descending",
+            },
+            column_code: self.column.code(),
+            order_code: self.order.code(),
+            dirs_first: self.dirs_first,
+            filter: self.filter.to_owned(),
+            sort_links,
+        }
+    }
+}
+
+/// A directory listing for machines, whose schema is kept stable.
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    uri: Option<String>,
+    parent_uri: Option<String>,
+    /// The latest modification time of the directory and its entries.
+    updated: Option<String>,
+    entries: Vec<ListingEntry>,
+    /// Whether `max_listing_entries` left out some entries.
+    truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListingEntry {
+    name: String,
+    uri: Option<String>,
+    /// Either `"directory"` or `"file"`.
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Size in bytes, for files.
+    size: Option<u64>,
+    /// RFC 3339 modification time.
+    modified: Option<String>,
+    mime_type: Option<String>,
+    /// Title from the entry's front matter.
+    title: Option<String>,
+}
+
+impl Listing {
+    pub fn from_context(context: &ResourceContext) -> Self {
+        let entries: Vec<ListingEntry> = context.children()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|child| {
+                let is_dir = child.metadata().is_some_and(|meta| meta.is_dir());
+
+                Some(ListingEntry {
+                    name: child.name()?.to_owned(),
+                    uri: child.uri().map(str::to_owned),
+                    kind: match is_dir {
+                        true => "directory",
+                        false => "file",
+                    },
+                    size: size(child),
+                    modified: modified(child).map(|modified| modified.to_rfc3339()),
+                    mime_type: match is_dir {
+                        true => None,
+                        false => mime_guess::from_path(child.name()?).first().map(|mime| mime.to_string()),
+                    },
+                    title: child.page()
+                        .and_then(|page| page.get("title"))
+                        .and_then(|title| title.as_str())
+                        .map(str::to_owned),
+                })
+            })
+            .collect();
+
+        let updated = context.children()
+            .unwrap_or_default()
+            .iter()
+            .chain(std::iter::once(context))
+            .filter_map(modified)
+            .max()
+            .map(|modified| modified.to_rfc3339());
+
+        Self {
+            uri: context.uri().map(str::to_owned),
+            parent_uri: context.parent_uri().map(str::to_owned),
+            updated,
+            entries,
+            truncated: context.truncated(),
+        }
+    }
+
+    /// One URI per line, directories ending in `/`.
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.uri.as_deref())
+            .map(|uri| format!("{}\n", uri))
+            .collect()
+    }
+}
+
+/// What templates can see of the listing's sort state, as `listing.*`.
+#[derive(Debug, Serialize)]
+pub struct ListingState {
+    column: &'static str,
+    order: &'static str,
+    column_code: &'static str,
+    order_code: &'static str,
+    dirs_first: bool,
+    filter: Option<String>,
+    sort_links: BTreeMap<&'static str, String>,
+}
+
+fn sort_name(child: &ResourceContext) -> Option<String> {
+    child.name().map(str::to_lowercase)
+}
+
+fn modified(child: &ResourceContext) -> Option<DateTime<FixedOffset>> {
+    child.metadata().and_then(|meta| DateTime::parse_from_rfc3339(meta.modified()).ok())
+}
+
+/// Directories have no meaningful size, so they sort before any file.
+fn size(child: &ResourceContext) -> Option<u64> {
+    child.metadata()
+        .filter(|meta| !meta.is_dir())
+        .map(|meta| meta.size())
+}
+
+fn extension(child: &ResourceContext) -> Option<String> {
+    let name = child.name()?;
+
+    match name.rfind('.') {
+        Some(index) if index > 0 => Some(name[index + 1..].to_lowercase()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    fn names(context: &ResourceContext) -> Vec<String> {
+        let value = serde_json::to_value(context).unwrap();
+
+        value["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| child["name"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn listing_schema_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("post.md"), "---\ntitle: Post\n---\nBody").unwrap();
+
+        let res = crate::Resource::new("/", &root, &crate::ConfigCache::default()).unwrap();
+        let mut context = res.context().clone();
+        ListingQuery::from_query(&query(&[]), true).apply(&mut context);
+
+        let listing = Listing::from_context(&context);
+        let value = serde_json::to_value(&listing).unwrap();
+
+        assert_eq!("/", value["uri"]);
+        assert_eq!(serde_json::Value::Null, value["parent_uri"]);
+        assert!(value["updated"].is_string());
+
+        assert_eq!("docs", value["entries"][0]["name"]);
+        assert_eq!("directory", value["entries"][0]["type"]);
+        assert_eq!(serde_json::Value::Null, value["entries"][0]["size"]);
+
+        assert_eq!("/post.md", value["entries"][1]["uri"]);
+        assert_eq!("file", value["entries"][1]["type"]);
+        assert_eq!(24, value["entries"][1]["size"]);
+        assert_eq!("text/markdown", value["entries"][1]["mime_type"]);
+        assert_eq!("Post", value["entries"][1]["title"]);
+
+        assert_eq!("/docs/\n/post.md\n", listing.to_text());
+    }
+
+    #[test]
+    fn sort_and_filter_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("b.txt"), "12345").unwrap();
+        fs::write(dir.path().join("A.md"), "1").unwrap();
+        fs::write(dir.path().join("c.TXT"), "123").unwrap();
+
+        let context = ResourceContext::from_path(dir.path(), false);
+
+        let listing = |pairs: &[(&str, &str)], dirs_first| {
+            let mut context = context.clone();
+            let query = ListingQuery::from_query(&query(pairs), dirs_first);
+
+            query.apply(&mut context);
+
+            (names(&context), query.state())
+        };
+
+        let (names, state) = listing(&[], false);
+        assert_eq!(vec!["A.md", "b.txt", "c.TXT", "docs"], names);
+        assert_eq!("?C=N;O=D", state.sort_links["name"]);
+        assert_eq!("?C=S;O=A", state.sort_links["size"]);
+
+        let (names, _) = listing(&[("C", "N"), ("O", "D")], true);
+        assert_eq!(vec!["docs", "c.TXT", "b.txt", "A.md"], names);
+
+        let (names, state) = listing(&[("C", "S"), ("O", "D"), ("P", "*.txt")], false);
+        assert_eq!(vec!["b.txt", "c.TXT"], names);
+        assert_eq!("?C=S;O=A;P=%2A.txt", state.sort_links["size"]);
+
+        let (_, state) = listing(&[("P", "*.md"), ("format", "json")], false);
+        assert_eq!("?C=N;O=D;P=%2A.md;format=json", state.sort_links["name"]);
+
+        let (names, _) = listing(&[("C", "T")], true);
+        assert_eq!(vec!["docs", "A.md", "b.txt", "c.TXT"], names);
+
+        // Invalid parameters fall back to the defaults
+        let (names, state) = listing(&[("C", "X"), ("O", "?"), ("P", "[")], false);
+        assert_eq!(vec!["A.md", "b.txt", "c.TXT", "docs"], names);
+        assert_eq!("name", state.column);
+    }
+}
\ No newline at end of file
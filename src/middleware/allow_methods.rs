@@ -1,17 +1,46 @@
-use roa::{Context, Next, Result, status, http};
+use roa::{Body, Context, Next, Result, status, http};
 
 use crate::{Resource, ServerConfig};
 
 pub async fn allow_methods<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     let res = ctx.load::<Resource>("res");
-    
-    if let Some(res) = res.as_deref() {
-        let ServerConfig { ref allow_methods, .. } = res.config().server;
-        
-        if allow_methods.iter().any(|method| method == ctx.method().as_ref()) {
-            return next.await;
-        }
+
+    let allowed = match res.as_deref() {
+        Some(res) => {
+            let ServerConfig { ref allow_methods, .. } = res.config().server;
+
+            let mut allowed: Vec<&str> = allow_methods.iter().map(String::as_str).collect();
+
+            // HEAD is always supported alongside GET
+            if allowed.contains(&"GET") && !allowed.contains(&"HEAD") {
+                allowed.push("HEAD");
+            }
+
+            allowed
+        },
+        None => Vec::new(),
+    };
+
+    ctx.resp.headers.insert("Allow", allowed.join(", ").parse()?);
+
+    if !allowed.contains(&ctx.method().as_ref()) {
+        return Err(status!(http::StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    if *ctx.method() == http::Method::OPTIONS {
+        ctx.resp.headers.insert("Content-Length", 0.into());
+
+        return Err(status!(http::StatusCode::NO_CONTENT));
+    }
+
+    ctx.resp.headers.remove("Allow");
+
+    let result = next.await;
+
+    // Keep the representation headers from GET, but never send a body
+    if *ctx.method() == http::Method::HEAD {
+        ctx.resp.body = Body::empty();
     }
 
-    Err(status!(http::StatusCode::METHOD_NOT_ALLOWED))
+    result
 }
\ No newline at end of file
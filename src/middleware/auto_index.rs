@@ -1,33 +1,191 @@
-use std::sync::Arc;
+use std::path::Path;
 
-use mime;
 use roa::{Context, Next, Result, status, http};
+use roa::http::HeaderMap;
+use serde::Serialize;
 
-use handlebars::Handlebars;
+use crate::{PolyState, Resource, ServerConfig};
+use crate::resource::ResourceContext;
+use crate::listing::{Listing, ListingQuery, ListingState};
+use super::{etag_from_content, cache_privately, convention_sources, index_sources, parse_query, parse_quality_list, vary_on, RequestContext};
 
-use crate::{Resource, ServerConfig};
+#[derive(Serialize)]
+struct IndexContext<'a> {
+    #[serde(flatten)]
+    resource: &'a ResourceContext,
+    request: RequestContext,
+    listing: ListingState,
+}
 
-pub async fn auto_index<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
+#[derive(Serialize)]
+struct FeedContext<'a> {
+    request: RequestContext,
+    listing: &'a Listing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListingFormat {
+    Html,
+    Json,
+    Atom,
+    Text,
+}
+
+impl ListingFormat {
+    /// In order of preference when the client accepts several equally.
+    pub const ALL: [ListingFormat; 4] = [ListingFormat::Html, ListingFormat::Json, ListingFormat::Atom, ListingFormat::Text];
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ListingFormat::Html => "text/html",
+            ListingFormat::Json => "application/json",
+            ListingFormat::Atom => "application/atom+xml",
+            ListingFormat::Text => "text/plain",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" => Some(ListingFormat::Html),
+            "json" => Some(ListingFormat::Json),
+            "atom" => Some(ListingFormat::Atom),
+            "text" | "txt" => Some(ListingFormat::Text),
+            _ => None,
+        }
+    }
+}
+
+pub async fn auto_index(ctx: &mut Context<PolyState>, next: Next<'_>) -> Result {
     let res = ctx.load::<Resource>("res");
 
     if let Some(res) = res.as_deref() {
-        let ServerConfig { ref auto_index, .. } = res.config().server;
+        let ServerConfig { ref auto_index, ref index_template, ref layout, ref dirs_first, .. } = res.config().server;
 
         if res.fs_path().is_dir() && *auto_index {
-            let hbs = ctx.load::<Arc<Handlebars>>("hbs");
+            let dirs: Vec<&Path> = res.ancestors()
+                .into_iter()
+                .filter(|path| path.is_dir())
+                .collect();
 
-            if let Some(hbs) = hbs.as_deref() {
-                let html = hbs.render("index", res.context())?;
+            let mut sources = convention_sources(&dirs, layout.as_str());
+            sources.extend(index_sources(&dirs, index_template.as_str()));
 
-                ctx.resp.headers.insert("Content-Length", html.len().into());
-                ctx.resp.headers.insert("Content-Type", mime::TEXT_HTML_UTF_8.as_ref().parse()?);
+            let hbs = ctx.templates().with_sources(&sources)?;
 
-                ctx.resp.write(html);
+            let params = parse_query(ctx.uri().query().unwrap_or_default());
+            let query = ListingQuery::from_query(&params, *dirs_first);
+            let format = select_format(params.get("format").map(String::as_str), &ctx.req.headers);
 
-                return Err(status!(http::StatusCode::OK));
+            let mut resource = match query.matcher() {
+                Some(matcher) => res.filtered_context(matcher),
+                None => res.context().clone(),
+            };
+
+            query.apply(&mut resource);
+
+            let body = match format {
+                ListingFormat::Html => hbs.render("index", &IndexContext {
+                    resource: &resource,
+                    request: RequestContext::from_context(ctx),
+                    listing: query.state(),
+                })?,
+                ListingFormat::Json => serde_json::to_string_pretty(&Listing::from_context(&resource))?,
+                ListingFormat::Atom => hbs.render("index_atom", &FeedContext {
+                    request: RequestContext::from_context(ctx),
+                    listing: &Listing::from_context(&resource),
+                })?,
+                ListingFormat::Text => Listing::from_context(&resource).to_text(),
+            };
+
+            let content_type = match format {
+                ListingFormat::Json => format.media_type().to_owned(),
+                _ => format!("{}; charset=utf-8", format.media_type()),
+            };
+
+            vary_on(&mut ctx.resp.headers, "Accept")?;
+
+            ctx.resp.headers.insert("Content-Length", body.len().into());
+            ctx.resp.headers.insert("Content-Type", content_type.parse()?);
+            ctx.resp.headers.insert("ETag", etag_from_content(body.as_bytes()).parse()?);
+
+            // Only templates see the request
+            if matches!(format, ListingFormat::Html | ListingFormat::Atom) {
+                cache_privately(&mut ctx.resp.headers)?;
             }
+
+            ctx.resp.write(body);
+
+            return Err(status!(http::StatusCode::OK));
         }
     }
 
     next.await
+}
+
+/// Picks the format named by `?format=`, or else the one with the highest
+/// q-value from `Accept`, where the most specific media range applies.
+/// Defaults to HTML.
+pub fn select_format(format: Option<&str>, headers: &HeaderMap) -> ListingFormat {
+    if let Some(format) = format.and_then(ListingFormat::from_name) {
+        return format;
+    }
+
+    let ranges = parse_quality_list(headers, "Accept");
+
+    if ranges.is_empty() {
+        return ListingFormat::Html;
+    }
+
+    let qvalue_of = |format: &ListingFormat| {
+        let media_type = format.media_type();
+        let type_range = format!("{}/*", media_type.split('/').next().unwrap_or_default());
+
+        [media_type, type_range.as_str(), "*/*"]
+            .iter()
+            .find_map(|candidate| ranges.iter().find(|(range, _)| range == candidate))
+            .map_or(0.0, |(_, qvalue)| *qvalue)
+    };
+
+    ListingFormat::ALL
+        .iter()
+        .map(|format| (*format, qvalue_of(format)))
+        .filter(|(_, qvalue)| *qvalue > 0.0)
+        .fold(None, |best: Option<(ListingFormat, f32)>, (format, qvalue)| match best {
+            Some((_, best_qvalue)) if best_qvalue >= qvalue => best,
+            _ => Some((format, qvalue)),
+        })
+        .map_or(ListingFormat::Html, |(format, _)| format)
+}
+
+#[cfg(test)]
+mod tests {
+    use roa::http::HeaderValue;
+
+    use super::*;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", HeaderValue::from_static(value));
+
+        headers
+    }
+
+    #[test]
+    fn select_format_test() {
+        let browser = accept("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8");
+
+        assert_eq!(ListingFormat::Html, select_format(None, &HeaderMap::new()));
+        assert_eq!(ListingFormat::Html, select_format(None, &accept("*/*")));
+        assert_eq!(ListingFormat::Html, select_format(None, &browser));
+        assert_eq!(ListingFormat::Json, select_format(None, &accept("application/json")));
+        assert_eq!(ListingFormat::Atom, select_format(None, &accept("application/atom+xml, text/html;q=0.5")));
+        assert_eq!(ListingFormat::Text, select_format(None, &accept("text/plain, text/*;q=0.5")));
+        assert_eq!(ListingFormat::Html, select_format(None, &accept("text/*, text/plain;q=0")));
+        assert_eq!(ListingFormat::Html, select_format(None, &accept("image/png")));
+        assert_eq!(ListingFormat::Json, select_format(None, &accept("text/html;Q=0.5, application/json")));
+
+        assert_eq!(ListingFormat::Json, select_format(Some("json"), &browser));
+        assert_eq!(ListingFormat::Text, select_format(Some("txt"), &browser));
+        assert_eq!(ListingFormat::Html, select_format(Some("yaml"), &browser));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+/// Most ranges a `Range` header may ask for before it is ignored, as each one
+/// is served from its own file handle.
+pub const MAX_RANGES: usize = 16;
+
+/// An inclusive range of bytes within a representation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, complete_len: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, complete_len)
+    }
+}
+
+/// Parses a `Range` header value against a representation of `complete_len` bytes.
+///
+/// Returns `None` if the header is not a valid byte range set, or asks for more
+/// than `MAX_RANGES` ranges, and should be ignored. Returns an empty list if none
+/// of the ranges are satisfiable. Overlapping and adjacent ranges are merged, in
+/// ascending order.
+pub fn parse_range(value: &str, complete_len: u64) -> Option<Vec<ByteRange>> {
+    let range_set = value.trim().strip_prefix("bytes=")?;
+
+    let specs: Vec<&str> = range_set
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect();
+
+    if specs.len() > MAX_RANGES {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+
+    for spec in specs {
+        let (first, last) = spec.split_once('-')?;
+
+        let range = match (first.trim(), last.trim()) {
+            // Suffix range, e.g. "-500"
+            ("", suffix) => {
+                let suffix_len: u64 = suffix.parse().ok()?;
+
+                match (suffix_len, complete_len) {
+                    (0, _) | (_, 0) => None,
+                    _ => Some(ByteRange {
+                        start: complete_len.saturating_sub(suffix_len),
+                        end: complete_len - 1,
+                    }),
+                }
+            },
+
+            // Open-ended range, e.g. "500-"
+            (first, "") => {
+                let start: u64 = first.parse().ok()?;
+
+                match start < complete_len {
+                    true => Some(ByteRange { start, end: complete_len - 1 }),
+                    false => None,
+                }
+            },
+
+            (first, last) => {
+                let start: u64 = first.parse().ok()?;
+                let end: u64 = last.parse().ok()?;
+
+                if end < start {
+                    return None;
+                }
+
+                match start < complete_len {
+                    true => Some(ByteRange { start, end: end.min(complete_len - 1) }),
+                    false => None,
+                }
+            },
+        };
+
+        ranges.extend(range);
+    }
+
+    Some(merge_ranges(ranges))
+}
+
+fn merge_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parse_range_test() {
+        assert_eq!(Some(vec![range(0, 499)]), parse_range("bytes=0-499", 1000));
+        assert_eq!(Some(vec![range(500, 999)]), parse_range("bytes=500-", 1000));
+        assert_eq!(Some(vec![range(900, 999)]), parse_range("bytes=-100", 1000));
+        assert_eq!(Some(vec![range(0, 999)]), parse_range("bytes=-5000", 1000));
+        assert_eq!(Some(vec![range(990, 999)]), parse_range("bytes=990-2000", 1000));
+        assert_eq!(
+            Some(vec![range(0, 0), range(10, 19)]),
+            parse_range("bytes=0-0, 10-19", 1000),
+        );
+    }
+
+    #[test]
+    fn parse_unsatisfiable_range_test() {
+        assert_eq!(Some(vec![]), parse_range("bytes=1000-", 1000));
+        assert_eq!(Some(vec![]), parse_range("bytes=-0", 1000));
+        assert_eq!(Some(vec![]), parse_range("bytes=0-10", 0));
+        assert_eq!(Some(vec![range(0, 9)]), parse_range("bytes=0-9,2000-3000", 1000));
+    }
+
+    #[test]
+    fn parse_overlapping_range_test() {
+        assert_eq!(Some(vec![range(0, 0)]), parse_range("bytes=0-0,0-0,0-0", 1000));
+        assert_eq!(Some(vec![range(0, 199)]), parse_range("bytes=100-199,0-149", 1000));
+        assert_eq!(Some(vec![range(0, 19)]), parse_range("bytes=0-9,10-19", 1000));
+        assert_eq!(Some(vec![range(0, 9), range(900, 999)]), parse_range("bytes=-100,0-9,950-", 1000));
+    }
+
+    #[test]
+    fn parse_too_many_ranges_test() {
+        let ranges = vec!["0-0"; MAX_RANGES + 1].join(",");
+
+        assert_eq!(Some(vec![range(0, 0)]), parse_range(&format!("bytes={}", &ranges[4..]), 1000));
+        assert_eq!(None, parse_range(&format!("bytes={}", ranges), 1000));
+    }
+
+    #[test]
+    fn parse_invalid_range_test() {
+        assert_eq!(None, parse_range("items=0-10", 1000));
+        assert_eq!(None, parse_range("bytes=10-5", 1000));
+        assert_eq!(None, parse_range("bytes=abc", 1000));
+        assert_eq!(None, parse_range("bytes=1-2-3", 1000));
+    }
+}
@@ -0,0 +1,224 @@
+use async_compression::Level;
+use async_compression::futures::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+use futures::TryStreamExt;
+use roa::{Context, Next, Result, http};
+use roa::http::HeaderMap;
+
+use crate::{Resource, ServerConfig};
+use crate::request_config::CompressLevel;
+use super::parse_quality_list;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// File extension of a precompressed sibling in this encoding.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gz"),
+            Encoding::Deflate => None,
+        }
+    }
+}
+
+pub async fn compress<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
+    let result = next.await;
+
+    match result {
+        Err(ref status) if status.status_code != http::StatusCode::OK => return result,
+        _ => (),
+    }
+
+    let res = ctx.load::<Resource>("res");
+
+    if let Some(res) = res.as_deref() {
+        let ServerConfig {
+            ref compress,
+            ref compress_min_length,
+            ref compress_types,
+            ref compress_level,
+            ..
+        } = res.config().server;
+
+        if !*compress || !is_compressible(&ctx.resp.headers, compress_types) {
+            return result;
+        }
+
+        // The representation varies by encoding even if we end up not compressing it
+        vary_on(&mut ctx.resp.headers, "Accept-Encoding")?;
+
+        let content_length = ctx.resp.headers
+            .get("Content-Length")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        if content_length.is_some_and(|len| len < *compress_min_length) {
+            return result;
+        }
+
+        if let Some(encoding) = select_encoding(&ctx.req.headers, &Encoding::ALL) {
+            let level = match compress_level {
+                CompressLevel::Fastest => Level::Fastest,
+                CompressLevel::Default => Level::Default,
+                CompressLevel::Best => Level::Best,
+            };
+
+            let body = std::mem::take(&mut ctx.resp.body).into_async_read();
+
+            match encoding {
+                Encoding::Brotli => ctx.resp.write_reader(BrotliEncoder::with_quality(body, level)),
+                Encoding::Gzip => ctx.resp.write_reader(GzipEncoder::with_quality(body, level)),
+                Encoding::Deflate => ctx.resp.write_reader(ZlibEncoder::with_quality(body, level)),
+            };
+
+            ctx.resp.headers.remove("Content-Length");
+            ctx.resp.headers.insert("Content-Encoding", encoding.as_str().parse()?);
+
+            // The encoded bytes differ from the identity representation
+            if let Some(etag) = ctx.resp.headers.get("ETag").and_then(|value| value.to_str().ok()) {
+                if !etag.starts_with("W/") {
+                    let etag = format!("W/{}", etag);
+
+                    ctx.resp.headers.insert("ETag", etag.parse()?);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn is_compressible(headers: &HeaderMap, compress_types: &[String]) -> bool {
+    if headers.contains_key("Content-Encoding") || headers.contains_key("Content-Range") {
+        return false;
+    }
+
+    let mime_type = headers
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok());
+
+    match mime_type {
+        Some(mime_type) => compress_types
+            .iter()
+            .filter_map(|pattern| pattern.split_once('/'))
+            .any(|(type_, subtype)| {
+                type_ == mime_type.type_() && (subtype == "*" || subtype == mime_type.subtype())
+            }),
+        None => false,
+    }
+}
+
+/// Adds `header` to `Vary`, unless it's already listed.
+pub fn vary_on(headers: &mut HeaderMap, header: &str) -> Result {
+    let is_present = headers
+        .get_all("Vary")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|name| name.trim().eq_ignore_ascii_case(header));
+
+    if !is_present {
+        headers.append("Vary", header.parse()?);
+    }
+
+    Ok(())
+}
+
+/// Picks the available encoding with the highest q-value from `Accept-Encoding`,
+/// preferring earlier entries in `available` on ties.
+pub fn select_encoding(headers: &HeaderMap, available: &[Encoding]) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut explicit = Vec::new();
+
+    for (coding, qvalue) in parse_quality_list(headers, "Accept-Encoding") {
+        match coding.as_str() {
+            "*" => wildcard = Some(qvalue),
+            _ => explicit.push((coding, qvalue)),
+        }
+    }
+
+    available
+        .iter()
+        .filter_map(|encoding| {
+            let qvalue = explicit
+                .iter()
+                .find(|(coding, _)| coding == encoding.as_str())
+                .map(|(_, qvalue)| *qvalue)
+                .or(wildcard)?;
+
+            match qvalue > 0.0 {
+                true => Some((*encoding, qvalue)),
+                false => None,
+            }
+        })
+        .fold(None, |best: Option<(Encoding, f32)>, (encoding, qvalue)| match best {
+            Some((_, best_qvalue)) if best_qvalue >= qvalue => best,
+            _ => Some((encoding, qvalue)),
+        })
+        .map(|(encoding, _)| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use roa::http::HeaderValue;
+
+    use super::*;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", HeaderValue::from_static(value));
+
+        headers
+    }
+
+    #[test]
+    fn select_encoding_test() {
+        assert_eq!(Some(Encoding::Brotli), select_encoding(&accept("gzip, deflate, br"), &Encoding::ALL));
+        assert_eq!(Some(Encoding::Gzip), select_encoding(&accept("gzip;q=1.0, br;q=0.5"), &Encoding::ALL));
+        assert_eq!(Some(Encoding::Deflate), select_encoding(&accept("deflate"), &Encoding::ALL));
+        assert_eq!(Some(Encoding::Brotli), select_encoding(&accept("*"), &Encoding::ALL));
+        assert_eq!(Some(Encoding::Gzip), select_encoding(&accept("br;q=0, *;q=0.1"), &Encoding::ALL));
+        assert_eq!(None, select_encoding(&accept("identity"), &Encoding::ALL));
+        assert_eq!(None, select_encoding(&accept("gzip;q=0"), &Encoding::ALL));
+        assert_eq!(Some(Encoding::Gzip), select_encoding(&accept("br;Q=0, gzip"), &Encoding::ALL));
+        assert_eq!(None, select_encoding(&HeaderMap::new(), &Encoding::ALL));
+
+        let precompressed = [Encoding::Gzip];
+        assert_eq!(Some(Encoding::Gzip), select_encoding(&accept("gzip, br"), &precompressed));
+    }
+
+    #[test]
+    fn is_compressible_test() {
+        let types = vec!["text/*".to_owned(), "application/javascript".to_owned()];
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("text/html; charset=utf-8"));
+        assert!(is_compressible(&headers, &types));
+
+        headers.insert("Content-Type", HeaderValue::from_static("application/javascript"));
+        assert!(is_compressible(&headers, &types));
+
+        headers.insert("Content-Type", HeaderValue::from_static("image/png"));
+        assert!(!is_compressible(&headers, &types));
+
+        headers.insert("Content-Type", HeaderValue::from_static("text/plain"));
+        headers.insert("Content-Encoding", HeaderValue::from_static("gzip"));
+        assert!(!is_compressible(&headers, &types));
+    }
+}
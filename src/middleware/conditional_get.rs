@@ -0,0 +1,215 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use roa::{Body, Context, Next, Result, status};
+use roa::http::{HeaderMap, Method, StatusCode};
+
+pub async fn conditional_get<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
+    let result = next.await;
+
+    // Only evaluate preconditions against a successfully selected representation
+    match result {
+        Err(ref status) if !status.status_code.is_success() => return result,
+        _ => (),
+    }
+
+    let status_code = evaluate_preconditions(ctx.method(), &ctx.req.headers, &ctx.resp.headers);
+
+    if let Some(status_code) = status_code {
+        ctx.resp.body = Body::empty();
+        ctx.resp.headers.remove("Content-Length");
+        ctx.resp.headers.remove("Content-Type");
+        ctx.resp.headers.remove("Content-Range");
+
+        return Err(status!(status_code));
+    }
+
+    result
+}
+
+/// Builds an entity tag from file size and modification time.
+///
+/// The tag is weak if the file was modified within the last second, as it could
+/// change again without its timestamp changing.
+pub fn etag_from_meta(meta: &fs::Metadata) -> String {
+    let modified = meta.modified().ok();
+
+    let since_epoch = modified
+        .and_then(|st| st.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    let is_recent = modified
+        .and_then(|st| SystemTime::now().duration_since(st).ok())
+        .is_none_or(|age| age < Duration::from_secs(1));
+
+    let tag = format!("\"{:x}-{:x}\"", meta.len(), since_epoch.as_nanos());
+
+    if is_recent {
+        format!("W/{}", tag)
+    } else {
+        tag
+    }
+}
+
+/// Builds a strong entity tag from a hash of the response content.
+pub fn etag_from_content(content: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(content);
+
+    format!("\"{:x}-{:016x}\"", content.len(), hasher.finish())
+}
+
+/// Strong comparison of two entity tags (RFC 7232, section 2.3.2).
+pub fn etag_strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+/// Weak comparison of two entity tags (RFC 7232, section 2.3.2).
+pub fn etag_weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// Evaluates request preconditions in the order given in RFC 7232, section 6.
+fn evaluate_preconditions(
+    method: &Method,
+    req_headers: &HeaderMap,
+    resp_headers: &HeaderMap,
+) -> Option<StatusCode> {
+    let is_get_or_head = method == Method::GET || method == Method::HEAD;
+
+    let etag = header_str(resp_headers, "ETag");
+    let last_modified = header_date(resp_headers, "Last-Modified");
+
+    if let Some(if_match) = header_str(req_headers, "If-Match") {
+        if !etag_list_matches(if_match, etag, etag_strong_eq) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    } else if let Some(since) = header_date(req_headers, "If-Unmodified-Since") {
+        if last_modified.is_some_and(|modified| modified > since) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+
+    if let Some(if_none_match) = header_str(req_headers, "If-None-Match") {
+        if etag_list_matches(if_none_match, etag, etag_weak_eq) {
+            return match is_get_or_head {
+                true => Some(StatusCode::NOT_MODIFIED),
+                false => Some(StatusCode::PRECONDITION_FAILED),
+            };
+        }
+    } else if let (true, Some(since)) = (is_get_or_head, header_date(req_headers, "If-Modified-Since")) {
+        if last_modified.is_some_and(|modified| modified <= since) {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+    }
+
+    None
+}
+
+fn etag_list_matches(list: &str, etag: Option<&str>, eq: fn(&str, &str) -> bool) -> bool {
+    match etag {
+        // A selected representation exists, so "*" always matches
+        Some(_) if list.trim() == "*" => true,
+
+        Some(etag) => list
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| eq(candidate, etag)),
+
+        None => false,
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn header_date(headers: &HeaderMap, name: &str) -> Option<SystemTime> {
+    header_str(headers, name)
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use roa::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+
+        map
+    }
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const EARLIER: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+
+    #[test]
+    fn etag_comparison_test() {
+        assert!(etag_strong_eq(r#""1""#, r#""1""#));
+        assert!(!etag_strong_eq(r#"W/"1""#, r#""1""#));
+        assert!(etag_weak_eq(r#"W/"1""#, r#""1""#));
+        assert!(!etag_weak_eq(r#""1""#, r#""2""#));
+    }
+
+    #[test]
+    fn if_none_match_test() {
+        let resp = headers(&[("ETag", r#""abc""#)]);
+
+        let req = headers(&[("If-None-Match", r#""xyz", W/"abc""#)]);
+        assert_eq!(Some(StatusCode::NOT_MODIFIED), evaluate_preconditions(&Method::GET, &req, &resp));
+
+        let req = headers(&[("If-None-Match", "*")]);
+        assert_eq!(Some(StatusCode::NOT_MODIFIED), evaluate_preconditions(&Method::HEAD, &req, &resp));
+
+        let req = headers(&[("If-None-Match", r#""xyz""#)]);
+        assert_eq!(None, evaluate_preconditions(&Method::GET, &req, &resp));
+    }
+
+    #[test]
+    fn if_match_test() {
+        let resp = headers(&[("ETag", r#"W/"abc""#)]);
+
+        // Weak tags never match strongly
+        let req = headers(&[("If-Match", r#"W/"abc""#)]);
+        assert_eq!(Some(StatusCode::PRECONDITION_FAILED), evaluate_preconditions(&Method::GET, &req, &resp));
+
+        let req = headers(&[("If-Match", "*")]);
+        assert_eq!(None, evaluate_preconditions(&Method::GET, &req, &resp));
+    }
+
+    #[test]
+    fn if_modified_since_test() {
+        let resp = headers(&[("Last-Modified", MODIFIED)]);
+
+        let req = headers(&[("If-Modified-Since", MODIFIED)]);
+        assert_eq!(Some(StatusCode::NOT_MODIFIED), evaluate_preconditions(&Method::GET, &req, &resp));
+
+        let req = headers(&[("If-Modified-Since", EARLIER)]);
+        assert_eq!(None, evaluate_preconditions(&Method::GET, &req, &resp));
+
+        // If-None-Match takes precedence
+        let resp = headers(&[("Last-Modified", MODIFIED), ("ETag", r#""abc""#)]);
+        let req = headers(&[("If-Modified-Since", MODIFIED), ("If-None-Match", r#""xyz""#)]);
+        assert_eq!(None, evaluate_preconditions(&Method::GET, &req, &resp));
+    }
+
+    #[test]
+    fn if_unmodified_since_test() {
+        let resp = headers(&[("Last-Modified", MODIFIED)]);
+
+        let req = headers(&[("If-Unmodified-Since", EARLIER)]);
+        assert_eq!(Some(StatusCode::PRECONDITION_FAILED), evaluate_preconditions(&Method::GET, &req, &resp));
+
+        let req = headers(&[("If-Unmodified-Since", MODIFIED)]);
+        assert_eq!(None, evaluate_preconditions(&Method::GET, &req, &resp));
+    }
+}
@@ -1,10 +1,15 @@
 use roa::{Context, Next, Result};
 
-pub async fn early_return<S>(_: &mut Context<S>, next: Next<'_>) -> Result {
+pub async fn early_return<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     match next.await {
         Err(status) if status.status_code.is_client_error() => Err(status),
         Err(status) if status.status_code.is_server_error() => Err(status),
         Err(status) if status.status_code.is_redirection() => Err(status),
+        Err(status) if status.status_code.is_success() => {
+            ctx.resp.status = status.status_code;
+
+            Ok(())
+        },
         _ => Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use roa::{Body, Context, Next, Result, status, http};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::{PolyState, Resource, ServerConfig};
+use crate::site_data::load_cascaded_data;
+use super::{convention_sources, error_sources, cache_privately, RequestContext};
+
+#[derive(Serialize)]
+struct ErrorContext<'a> {
+    title: String,
+    status: u16,
+    reason: &'a str,
+    message: Option<&'a str>,
+    request: RequestContext,
+    data: JsonValue,
+}
+
+/// Renders error responses with the nearest error template, or the built-in one.
+pub async fn error_page(ctx: &mut Context<PolyState>, next: Next<'_>) -> Result {
+    let status = match next.await {
+        Err(status) if status.status_code.is_client_error() || status.status_code.is_server_error() => status,
+        result => return result,
+    };
+
+    // Dev mode diagnostics have already written a body
+    if ctx.load::<bool>("diagnostics").is_some() {
+        return Err(status);
+    }
+
+    let res = ctx.load::<Resource>("res");
+
+    // A request that never resolved, e.g. to a hidden path, only gets the root's pages
+    let dirs: Vec<PathBuf> = match res.as_deref() {
+        Some(res) => res.ancestors()
+            .into_iter()
+            .filter(|path| path.is_dir())
+            .map(Path::to_owned)
+            .collect(),
+        None => vec![ctx.root_path().to_owned()],
+    };
+
+    let dirs: Vec<&Path> = dirs.iter().map(PathBuf::as_path).collect();
+    let code = status.status_code.as_u16();
+
+    // Without a valid configuration, only the built-in page is used
+    let (hbs, is_custom) = match ctx.config_cache().get(&dirs) {
+        Ok(config) => {
+            let ServerConfig { ref layout, ref error_templates, .. } = config.server;
+
+            let mut sources = convention_sources(&dirs, layout.as_str());
+            sources.extend(error_sources(&dirs, error_templates, code));
+
+            match ctx.templates().with_sources(&sources) {
+                // Without any sources from the web root, this is the base registry
+                Ok(hbs) => {
+                    let is_custom = !Arc::ptr_eq(&hbs, &ctx.templates().base());
+
+                    (hbs, is_custom)
+                },
+                Err(err) => {
+                    log::warn!("Error page for {} failed to compile: {}", code, err);
+
+                    (ctx.templates().base(), false)
+                },
+            }
+        },
+        Err(_) => (ctx.templates().base(), false),
+    };
+
+    // Only messages meant for the client are shown, unless in dev mode
+    let message = match status.expose || ctx.dev_mode() {
+        true => Some(status.message.as_str()).filter(|message| !message.is_empty()),
+        false => None,
+    };
+
+    let reason = status.status_code.canonical_reason().unwrap_or_default();
+
+    let context = ErrorContext {
+        title: format!("{} {}", code, reason),
+        status: code,
+        reason,
+        message,
+        request: RequestContext::from_context(ctx),
+        // The built-in templates never use data, so only read it for the site's own
+        data: match is_custom {
+            true => load_cascaded_data(&dirs),
+            false => JsonValue::Null,
+        },
+    };
+
+    let html = match hbs.render("error", &context) {
+        Ok(html) => html,
+        Err(err) => {
+            log::warn!("Error page for {} failed to render: {}", code, err);
+
+            ctx.templates().base().render("error", &context)?
+        },
+    };
+
+    // Validators and encodings of the resource don't apply to the error page
+    ctx.resp.headers.remove("ETag");
+    ctx.resp.headers.remove("Last-Modified");
+    ctx.resp.headers.remove("Content-Encoding");
+
+    ctx.resp.headers.insert("Content-Length", html.len().into());
+    ctx.resp.headers.insert("Content-Type", mime::TEXT_HTML_UTF_8.as_ref().parse()?);
+    cache_privately(&mut ctx.resp.headers)?;
+
+    // Keep the representation headers for HEAD, but never send a body
+    if *ctx.method() == http::Method::HEAD {
+        ctx.resp.body = Body::empty();
+    } else {
+        ctx.resp.write(html);
+    }
+
+    // An exposed message would otherwise be appended to the page
+    match status.expose {
+        true => Err(status!(status.status_code)),
+        false => Err(status),
+    }
+}
\ No newline at end of file
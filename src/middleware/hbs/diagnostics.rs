@@ -0,0 +1,22 @@
+use handlebars::{Handlebars, RenderError};
+use serde::Serialize;
+
+use crate::request_config::ConfigError;
+
+#[derive(Serialize)]
+struct ConfigErrorContext<'a> {
+    title: &'a str,
+    uri_path: &'a str,
+    errors: &'a [ConfigError],
+}
+
+/// Renders a diagnostic page listing configuration errors, for dev mode.
+pub fn render_config_errors(hbs: &Handlebars, uri_path: &str, errors: &[ConfigError]) -> Result<String, RenderError> {
+    let context = ConfigErrorContext {
+        title: "configuration error",
+        uri_path,
+        errors,
+    };
+
+    hbs.render("config_error", &context)
+}
\ No newline at end of file
@@ -1,4 +1,32 @@
-use handlebars::{Handlebars, Helper, Context, RenderContext, Output, RenderError};
+use std::fmt::Write;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate};
+use chrono::format::{Item, StrftimeItems};
+use handlebars::{handlebars_helper, Handlebars, Helper, Context, RenderContext, Output, RenderError, JsonValue};
+
+use crate::middleware::untrusted_markdown_to_html;
+
+/// Registers every built-in helper, in addition to the comparison and logic
+/// helpers (`eq`, `ne`, `gt`, `gte`, `lt`, `lte`, `and`, `or`, `not`, `len`)
+/// that handlebars provides.
+pub fn register_helpers(hbs: &mut Handlebars) {
+    hbs.register_helper("url_encode", Box::new(url_encode_helper));
+    hbs.register_helper("url_decode", Box::new(url_decode_helper));
+    hbs.register_helper("format_date", Box::new(format_date_helper));
+    hbs.register_helper("filesize", Box::new(filesize_helper));
+    hbs.register_helper("upper", Box::new(upper_helper));
+    hbs.register_helper("lower", Box::new(lower_helper));
+    hbs.register_helper("capitalize", Box::new(capitalize_helper));
+    hbs.register_helper("truncate", Box::new(truncate_helper));
+    hbs.register_helper("replace", Box::new(replace_helper));
+    hbs.register_helper("json", Box::new(json_helper));
+    hbs.register_helper("path_join", Box::new(path_join_helper));
+    hbs.register_helper("dirname", Box::new(dirname_helper));
+    hbs.register_helper("basename", Box::new(basename_helper));
+    hbs.register_helper("extension", Box::new(extension_helper));
+    hbs.register_helper("markdown", Box::new(markdown_helper));
+}
 
 pub fn url_encode_helper(
     h: &Helper,
@@ -9,7 +37,7 @@ pub fn url_encode_helper(
 ) -> Result<(), RenderError> {
     // get parameter from helper or throw an error
     let s: String = h.param(0)
-        .and_then(|param| Some(param.render()))
+        .map(|param| param.render())
         .ok_or(RenderError::new("Input parameter 0 is required for url_encode helper."))?;
     
     let rendered = urlencoding::encode(s.as_str());
@@ -28,7 +56,7 @@ pub fn url_decode_helper(
 ) -> Result<(), RenderError> {
     // get parameter from helper or throw an error
     let s: String = h.param(0)
-        .and_then(|param| Some(param.render()))
+        .map(|param| param.render())
         .ok_or(RenderError::new("Input parameter 0 is required for url_encode helper."))?;
     
     let rendered = urlencoding::decode(s.as_str())
@@ -39,9 +67,159 @@ pub fn url_decode_helper(
     Ok(())
 }
 
+// Formats an RFC 3339 timestamp or a plain date with a strftime format,
+// leaving anything else as is
+handlebars_helper!(format_date_helper: |value: str, { format: str = "%Y-%m-%d" }| {
+    format_date(value, format)
+});
+
+handlebars_helper!(filesize_helper: |bytes: u64| {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[0]),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+});
+
+handlebars_helper!(upper_helper: |value: str| value.to_uppercase());
+handlebars_helper!(lower_helper: |value: str| value.to_lowercase());
+
+handlebars_helper!(capitalize_helper: |value: str| {
+    let mut chars = value.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+        None => String::new(),
+    }
+});
+
+handlebars_helper!(truncate_helper: |value: str, length: u64, { suffix: str = "…" }| {
+    match value.chars().count() as u64 > length {
+        true => value.chars().take(length as usize).collect::<String>() + suffix,
+        false => value.to_owned(),
+    }
+});
+
+handlebars_helper!(replace_helper: |value: str, from: str, to: str| value.replace(from, to));
+
+handlebars_helper!(json_helper: |value: Json, { pretty: bool = false }| {
+    let json = match pretty {
+        true => serde_json::to_string_pretty(value),
+        false => serde_json::to_string(value),
+    };
+
+    json.unwrap_or_default()
+});
+
+handlebars_helper!(path_join_helper: |*args| {
+    let segments: Vec<String> = args
+        .iter()
+        .map(|arg| match arg {
+            JsonValue::String(value) => value.to_owned(),
+            value => value.to_string(),
+        })
+        .collect();
+
+    let mut joined = String::new();
+
+    for (index, segment) in segments.iter().enumerate() {
+        let segment = match index {
+            0 => segment.trim_end_matches('/'),
+            _ => segment.trim_matches('/'),
+        };
+
+        if index > 0 {
+            joined.push('/');
+        }
+
+        joined.push_str(segment);
+    }
+
+    joined
+});
+
+handlebars_helper!(dirname_helper: |value: str| {
+    Path::new(value)
+        .parent()
+        .map(|parent| parent.to_string_lossy().into_owned())
+        .unwrap_or_default()
+});
+
+handlebars_helper!(basename_helper: |value: str| {
+    Path::new(value)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+});
+
+handlebars_helper!(extension_helper: |value: str| {
+    Path::new(value)
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned())
+        .unwrap_or_default()
+});
+
+/// Renders markdown to HTML, without a wrapping paragraph for single lines. Its
+/// input may come from the request, so raw HTML in it is escaped.
+pub fn markdown_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    let s: String = h.param(0)
+        .map(|param| param.render())
+        .ok_or(RenderError::new("Input parameter 0 is required for markdown helper."))?;
+
+    let html = untrusted_markdown_to_html(s.as_str());
+    let trimmed = html.trim_end();
+
+    let inline = trimmed
+        .strip_prefix("<p>")
+        .and_then(|html| html.strip_suffix("</p>"))
+        .filter(|html| !html.contains("<p>"));
+
+    out.write(inline.unwrap_or(trimmed))?;
+
+    Ok(())
+}
+
+fn format_date(value: &str, format: &str) -> String {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return value.to_owned();
+    }
+
+    let mut formatted = String::new();
+
+    let result = match DateTime::parse_from_rfc3339(value) {
+        Ok(date_time) => write!(formatted, "{}", date_time.format(format)),
+        Err(_) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => write!(formatted, "{}", date.format(format)),
+            Err(_) => return value.to_owned(),
+        },
+    };
+
+    // e.g. formatting a time of day for a plain date
+    match result {
+        Ok(_) => formatted,
+        Err(_) => value.to_owned(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use handlebars::Handlebars;
+    use serde_json::json;
 
     use super::*;
 
@@ -62,4 +240,86 @@ mod tests {
         let rendered = hbs.render_template(r##"{{ url_decode "one%20two%20three" }}"##, &()).unwrap();
         assert_eq!(r##"one two three"##, rendered);
     }
+
+    fn render(template: &str, data: &JsonValue) -> String {
+        let mut hbs = Handlebars::new();
+        register_helpers(&mut hbs);
+
+        hbs.render_template(template, data).unwrap()
+    }
+
+    #[test]
+    fn format_date_test() {
+        let data = json!({ "modified": "2022-03-04T05:06:07.123+00:00", "date": "2022-03-04" });
+
+        assert_eq!("2022-03-04", render("{{format_date modified}}", &data));
+        assert_eq!("04/03/2022 05:06", render(r#"{{format_date modified format="%d/%m/%Y %H:%M"}}"#, &data));
+        assert_eq!("March 4, 2022", render(r#"{{format_date date format="%B %-d, %Y"}}"#, &data));
+        assert_eq!("(unknown)", render("{{format_date \"(unknown)\"}}", &data));
+        assert_eq!("2022-03-04", render(r#"{{format_date date format="%H:%M"}}"#, &data));
+        assert_eq!("2022-03-04", render(r#"{{format_date date format="%Q"}}"#, &data));
+    }
+
+    #[test]
+    fn filesize_test() {
+        assert_eq!("512 B", render("{{filesize 512}}", &json!({})));
+        assert_eq!("1.5 KiB", render("{{filesize 1536}}", &json!({})));
+        assert_eq!("3.0 GiB", render("{{filesize 3221225472}}", &json!({})));
+    }
+
+    #[test]
+    fn string_helpers_test() {
+        let data = json!({ "s": "hello <world>" });
+
+        assert_eq!("HELLO &lt;WORLD&gt;", render("{{upper s}}", &data));
+        assert_eq!("hello", render(r#"{{lower "HELLO"}}"#, &data));
+        assert_eq!("Élan", render(r#"{{capitalize "élan"}}"#, &data));
+        assert_eq!("hello…", render("{{truncate s 5}}", &data));
+        assert_eq!("hel...", render(r#"{{truncate s 3 suffix="..."}}"#, &data));
+        assert_eq!("hi", render(r#"{{truncate "hi" 5}}"#, &data));
+        assert_eq!("hello-<world>", render(r#"{{{replace s " " "-"}}}"#, &data));
+        assert_eq!("HELLO-&lt;WORLD&gt;", render(r#"{{upper (replace s " " "-")}}"#, &data));
+    }
+
+    #[test]
+    fn logic_helpers_test() {
+        let data = json!({ "a": 1, "b": 2, "s": "x" });
+
+        assert_eq!("yes", render(r#"{{#if (eq s "x")}}yes{{/if}}"#, &data));
+        assert_eq!("yes", render("{{#if (gt b a)}}yes{{/if}}", &data));
+        assert_eq!("yes", render("{{#if (and a (or missing b))}}yes{{/if}}", &data));
+        assert_eq!("yes", render("{{#if (not missing)}}yes{{/if}}", &data));
+    }
+
+    #[test]
+    fn json_helper_test() {
+        let data = json!({ "page": { "tags": ["a", "b"] } });
+
+        assert_eq!(r#"{"tags":["a","b"]}"#, render("{{{json page}}}", &data));
+        assert_eq!("[\n  \"a\",\n  \"b\"\n]", render("{{{json page.tags pretty=true}}}", &data));
+    }
+
+    #[test]
+    fn path_helpers_test() {
+        let data = json!({ "path": "/docs/guide/intro.md" });
+
+        assert_eq!("/docs/guide/a/b.md", render(r#"{{path_join "/docs/" "guide/" "/a" "b.md"}}"#, &data));
+        assert_eq!("/docs/guide", render("{{dirname path}}", &data));
+        assert_eq!("intro.md", render("{{basename path}}", &data));
+        assert_eq!("md", render("{{extension path}}", &data));
+        assert_eq!("", render(r#"{{extension "README"}}"#, &data));
+    }
+
+    #[test]
+    fn markdown_helper_test() {
+        let data = json!({ "text": "Some *emphasis* and `code`" });
+
+        assert_eq!("Some <em>emphasis</em> and <code>code</code>", render("{{markdown text}}", &data));
+        assert_eq!("<p>one</p>\n<p>two</p>", render(r#"{{markdown "one\n\ntwo"}}"#, &data));
+
+        let data = json!({ "q": "<script>alert(1)</script>", "link": "<b>bold</b> [x](JavaScript:alert(1))" });
+        assert_eq!("&lt;script&gt;alert(1)&lt;/script&gt;", render("{{markdown q}}", &data));
+        assert_eq!("&lt;b&gt;bold&lt;/b&gt; <a href=\"#\">x</a>", render("{{markdown link}}", &data));
+        assert_eq!("&lt;div&gt;block&lt;/div&gt;", render(r#"{{markdown "<div>block</div>"}}"#, &data));
+    }
 }
\ No newline at end of file
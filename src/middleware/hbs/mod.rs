@@ -1,5 +1,13 @@
 mod render_hbs;
 mod helpers;
+mod diagnostics;
+mod registry;
+mod request_context;
+mod scripting;
 
 pub use render_hbs::render_hbs;
-use helpers::{url_encode_helper, url_decode_helper};
\ No newline at end of file
+pub use diagnostics::render_config_errors;
+pub use request_context::{RequestContext, PageContext, parse_query};
+pub use registry::{TemplateRegistry, TemplateSource, convention_sources, index_sources, error_sources};
+pub use registry::{LAYOUT_FILE_NAME, LAYOUTS_DIR_NAME, PARTIALS_DIR_NAME, HELPERS_DIR_NAME};
+use helpers::register_helpers;
\ No newline at end of file
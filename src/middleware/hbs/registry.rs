@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use handlebars::{Handlebars, TemplateError};
+
+use crate::FileStamp;
+use crate::front_matter::split_front_matter;
+use super::register_helpers;
+use super::scripting::{script_engine, ScriptErrorHelper};
+
+const INDEX_TEMPLATE: &str = include_str!("../../../include/templates/index.html.hbs");
+const INDEX_ATOM_TEMPLATE: &str = include_str!("../../../include/templates/index.atom.hbs");
+const LAYOUT_TEMPLATE: &str = include_str!("../../../include/templates/layout.html.hbs");
+const MARKDOWN_TEMPLATE: &str = include_str!("../../../include/templates/markdown.html.hbs");
+const CONFIG_ERROR_TEMPLATE: &str = include_str!("../../../include/templates/config_error.html.hbs");
+const ERROR_TEMPLATE: &str = include_str!("../../../include/templates/error.html.hbs");
+
+/// Overrides the `layout` partial for pages in its directory and below.
+pub const LAYOUT_FILE_NAME: &str = "_layout.hbs";
+/// Holds named layouts, selected with the `layout` config key.
+pub const LAYOUTS_DIR_NAME: &str = "_layouts";
+/// Holds partials, registered by file stem for pages in its directory and below.
+pub const PARTIALS_DIR_NAME: &str = "_partials";
+/// Holds `.rhai` script helpers, registered by file stem like partials.
+pub const HELPERS_DIR_NAME: &str = "_helpers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceKind {
+    Template,
+    Partial,
+    Script,
+}
+
+/// A template file to compile into a registry under `name`.
+#[derive(Debug, Clone)]
+pub struct TemplateSource {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: SourceKind,
+}
+
+impl TemplateSource {
+    pub fn template(name: &str, path: &Path) -> Self {
+        Self { name: name.to_owned(), path: path.to_owned(), kind: SourceKind::Template }
+    }
+
+    pub fn partial(name: &str, path: &Path) -> Self {
+        Self { name: name.to_owned(), path: path.to_owned(), kind: SourceKind::Partial }
+    }
+
+    pub fn script(name: &str, path: &Path) -> Self {
+        Self { name: name.to_owned(), path: path.to_owned(), kind: SourceKind::Script }
+    }
+}
+
+/// Layouts, partials and script helpers found by convention in `dirs`, ordered
+/// deepest first.
+///
+/// Sources are ordered so the nearest directory wins, and a layout named by
+/// `layout` wins over any `_layout.hbs`.
+pub fn convention_sources(dirs: &[&Path], layout: &str) -> Vec<TemplateSource> {
+    let mut sources = Vec::new();
+
+    for dir in dirs.iter().rev() {
+        sources.extend(files_with_extension(dir.join(HELPERS_DIR_NAME).as_path(), "rhai")
+            .iter()
+            .filter_map(|path| Some(TemplateSource::script(path.file_stem()?.to_str()?, path))));
+
+        sources.extend(files_with_extension(dir.join(PARTIALS_DIR_NAME).as_path(), "hbs")
+            .iter()
+            .filter_map(|path| Some(TemplateSource::partial(path.file_stem()?.to_str()?, path))));
+
+        sources.push(TemplateSource::partial("layout", dir.join(LAYOUT_FILE_NAME).as_path()));
+    }
+
+    if !layout.is_empty() {
+        for dir in dirs.iter().rev() {
+            let path = dir.join(LAYOUTS_DIR_NAME).join(format!("{}.hbs", layout));
+
+            sources.push(TemplateSource::partial("layout", path.as_path()));
+        }
+    }
+
+    sources
+}
+
+/// Files in `dir` with `extension`, sorted so unchanged directories hit the cache.
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match dir.read_dir() {
+        Ok(read_dir) => read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == extension))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    paths.sort();
+
+    paths
+}
+
+/// Candidates for the directory listing template named `file_name` in `dirs`,
+/// ordered deepest first, so the nearest one replaces the built-in `index`.
+pub fn index_sources(dirs: &[&Path], file_name: &str) -> Vec<TemplateSource> {
+    match file_name.is_empty() {
+        true => Vec::new(),
+        false => dirs
+            .iter()
+            .rev()
+            .map(|dir| TemplateSource::template("index", dir.join(file_name).as_path()))
+            .collect(),
+    }
+}
+
+/// Candidates for the error page template for `status` in `dirs`, from names
+/// ordered most specific first, e.g. `_{status}.hbs`. The nearest directory
+/// wins, then the most specific name within it, replacing the built-in `error`.
+pub fn error_sources(dirs: &[&Path], names: &[String], status: u16) -> Vec<TemplateSource> {
+    let file_names: Vec<String> = names
+        .iter()
+        .rev()
+        .map(|name| name
+            .replace("{status}", status.to_string().as_str())
+            .replace("{class}", (status / 100).to_string().as_str()))
+        .collect();
+
+    dirs.iter()
+        .rev()
+        .flat_map(|dir| file_names
+            .iter()
+            .map(move |file_name| TemplateSource::template("error", dir.join(file_name).as_path())))
+        .collect()
+}
+
+/// Long-lived Handlebars registries, shared between requests.
+///
+/// The built-in templates and helpers are registered once. Template files from
+/// the web root are compiled on top of them once per set of existing sources,
+/// so directories with the same conventions share a registry. Registries are
+/// dropped once any of their sources change or disappear.
+#[derive(Debug, Clone)]
+pub struct TemplateRegistry {
+    base: Arc<Handlebars<'static>>,
+    compiled: Arc<RwLock<HashMap<Vec<SourceStamp>, Arc<Handlebars<'static>>>>>,
+}
+
+/// An existing source as it was compiled.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SourceStamp {
+    name: String,
+    kind: SourceKind,
+    stamp: FileStamp,
+}
+
+impl Default for TemplateRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        let mut hbs = Handlebars::new();
+
+        hbs.set_engine(script_engine());
+        register_helpers(&mut hbs);
+
+        // Built-in templates are known to compile
+        hbs.register_template_string("index", INDEX_TEMPLATE).unwrap();
+        hbs.register_template_string("index_atom", INDEX_ATOM_TEMPLATE).unwrap();
+        hbs.register_template_string("markdown", MARKDOWN_TEMPLATE).unwrap();
+        hbs.register_template_string("config_error", CONFIG_ERROR_TEMPLATE).unwrap();
+        hbs.register_template_string("error", ERROR_TEMPLATE).unwrap();
+        hbs.register_partial("layout", LAYOUT_TEMPLATE).unwrap();
+
+        Self {
+            base: Arc::new(hbs),
+            compiled: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// The registry with only built-in templates and helpers.
+    pub fn base(&self) -> Arc<Handlebars<'static>> {
+        Arc::clone(&self.base)
+    }
+
+    /// The base registry with `sources` compiled on top, in order. Sources that
+    /// don't exist are skipped.
+    pub fn with_sources(&self, sources: &[TemplateSource]) -> Result<Arc<Handlebars<'static>>, Box<TemplateError>> {
+        let (sources, key): (Vec<&TemplateSource>, Vec<SourceStamp>) = sources
+            .iter()
+            .map(|source| (source, FileStamp::of(source.path.as_path())))
+            .filter(|(_, stamp)| stamp.exists())
+            .map(|(source, stamp)| (source, SourceStamp { name: source.name.to_owned(), kind: source.kind, stamp }))
+            .unzip();
+
+        if key.is_empty() {
+            return Ok(self.base());
+        }
+
+        if let Ok(compiled) = self.compiled.read() {
+            if let Some(hbs) = compiled.get(&key) {
+                return Ok(Arc::clone(hbs));
+            }
+        }
+
+        let mut hbs = (*self.base).clone();
+
+        for source in sources {
+            let mut contents = fs::read_to_string(&source.path)
+                .map_err(|err| TemplateError::from((err, source.name.to_owned())))?;
+
+            if let Some((_, len)) = split_front_matter(contents.as_str()) {
+                contents.replace_range(..len, "");
+            }
+
+            match source.kind {
+                SourceKind::Template => hbs.register_template_string(source.name.as_str(), contents)?,
+                SourceKind::Partial => hbs.register_partial(source.name.as_str(), contents)?,
+                SourceKind::Script => {
+                    if let Err(err) = hbs.register_script_helper(source.name.as_str(), contents.as_str()) {
+                        log::warn!("Script helper {:?} failed to compile: {}", source.path, err);
+
+                        hbs.register_helper(source.name.as_str(), Box::new(ScriptErrorHelper {
+                            name: source.name.to_owned(),
+                            message: err.to_string(),
+                        }));
+                    }
+                },
+            }
+        }
+
+        let hbs = Arc::new(hbs);
+
+        if let Ok(mut compiled) = self.compiled.write() {
+            // Anything compiled from since changed files would never be used again
+            compiled.retain(|key, _| key.iter().all(|source| source.stamp.is_current()));
+            compiled.insert(key, Arc::clone(&hbs));
+        }
+
+        Ok(hbs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn recompile_on_change_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = dir.path().join("page.hbs");
+        let partial = dir.path().join("greeting.hbs");
+
+        let registry = TemplateRegistry::new();
+        let sources = [
+            TemplateSource::partial("greeting", &partial),
+            TemplateSource::template("page", &page),
+        ];
+
+        fs::write(&page, "{{> greeting}} {{name}}").unwrap();
+        fs::write(&partial, "hello").unwrap();
+
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert_eq!("hello world", hbs.render("page", &json!({ "name": "world" })).unwrap());
+        assert!(Arc::ptr_eq(&hbs, &registry.with_sources(&sources).unwrap()));
+
+        fs::write(&partial, "goodbye").unwrap();
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert_eq!("goodbye world", hbs.render("page", &json!({ "name": "world" })).unwrap());
+
+        // Missing sources are skipped until they appear
+        fs::remove_file(&partial).unwrap();
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert!(!hbs.has_template("greeting"));
+
+        // Built-in templates are unaffected
+        assert!(!registry.base().has_template("page"));
+    }
+
+    #[test]
+    fn shared_registry_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let (a, b) = (root.join("a"), root.join("b"));
+
+        fs::create_dir(&a).unwrap();
+        fs::create_dir(&b).unwrap();
+
+        let registry = TemplateRegistry::new();
+
+        // Directories without templates of their own get the base registry
+        let hbs = registry.with_sources(&convention_sources(&[a.as_path(), root], "")).unwrap();
+        assert!(Arc::ptr_eq(&registry.base(), &hbs));
+
+        fs::write(root.join(LAYOUT_FILE_NAME), "one").unwrap();
+        let hbs_a = registry.with_sources(&convention_sources(&[a.as_path(), root], "")).unwrap();
+        let hbs_b = registry.with_sources(&convention_sources(&[b.as_path(), root], "")).unwrap();
+        assert!(Arc::ptr_eq(&hbs_a, &hbs_b));
+        assert_eq!(1, registry.compiled.read().unwrap().len());
+
+        // Registries compiled from a changed or deleted file are dropped
+        fs::write(root.join(LAYOUT_FILE_NAME), "two!").unwrap();
+        fs::write(a.join(LAYOUT_FILE_NAME), "a").unwrap();
+        registry.with_sources(&convention_sources(&[a.as_path(), root], "")).unwrap();
+        assert_eq!(1, registry.compiled.read().unwrap().len());
+
+        fs::remove_file(a.join(LAYOUT_FILE_NAME)).unwrap();
+        registry.with_sources(&convention_sources(&[b.as_path(), root], "")).unwrap();
+        assert_eq!(1, registry.compiled.read().unwrap().len());
+    }
+
+    #[test]
+    fn convention_sources_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+
+        fs::create_dir_all(root.join(PARTIALS_DIR_NAME)).unwrap();
+        fs::create_dir_all(sub.join(PARTIALS_DIR_NAME)).unwrap();
+        fs::create_dir_all(root.join(LAYOUTS_DIR_NAME)).unwrap();
+
+        fs::write(root.join(LAYOUT_FILE_NAME), "root {{> body}}").unwrap();
+        fs::write(sub.join(LAYOUT_FILE_NAME), "sub {{> body}}").unwrap();
+        fs::write(root.join(LAYOUTS_DIR_NAME).join("blog.hbs"), "blog {{> body}}").unwrap();
+        fs::write(root.join(PARTIALS_DIR_NAME).join("nav.hbs"), "root-nav").unwrap();
+        fs::write(root.join(PARTIALS_DIR_NAME).join("footer.hbs"), "footer").unwrap();
+        fs::write(sub.join(PARTIALS_DIR_NAME).join("nav.hbs"), "sub-nav").unwrap();
+
+        let page = sub.join("page.hbs");
+        fs::write(&page, "{{#*inline \"body\"}}{{> nav}} {{> footer}}{{/inline}}{{> layout}}").unwrap();
+
+        let registry = TemplateRegistry::new();
+        let dirs = [sub.as_path(), root];
+
+        let mut sources = convention_sources(&dirs, "");
+        sources.push(TemplateSource::template("page", &page));
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert_eq!("sub sub-nav footer", hbs.render("page", &()).unwrap());
+
+        let mut sources = convention_sources(&dirs, "blog");
+        sources.push(TemplateSource::template("page", &page));
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert_eq!("blog sub-nav footer", hbs.render("page", &()).unwrap());
+    }
+
+    #[test]
+    fn error_sources_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(root.join("_404.hbs"), "root 404").unwrap();
+        fs::write(root.join("_error.hbs"), "root error").unwrap();
+        fs::write(sub.join("_4xx.hbs"), "sub 4xx").unwrap();
+
+        let names: Vec<String> = ["_{status}.hbs", "_{class}xx.hbs", "_error.hbs"]
+            .iter()
+            .map(|&name| name.to_owned())
+            .collect();
+
+        let registry = TemplateRegistry::new();
+        let render = |dirs: &[&Path], status: u16| {
+            let hbs = registry.with_sources(&error_sources(dirs, &names, status)).unwrap();
+
+            hbs.render("error", &()).unwrap()
+        };
+
+        assert_eq!("root 404", render(&[root], 404));
+        assert_eq!("root error", render(&[root], 403));
+        assert_eq!("sub 4xx", render(&[sub.as_path(), root], 404));
+        assert_eq!("root error", render(&[sub.as_path(), root], 500));
+    }
+
+    #[test]
+    fn script_helper_sources_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+
+        fs::create_dir_all(root.join(HELPERS_DIR_NAME)).unwrap();
+        fs::create_dir_all(sub.join(HELPERS_DIR_NAME)).unwrap();
+
+        fs::write(root.join(HELPERS_DIR_NAME).join("shout.rhai"), "params[0].to_upper()").unwrap();
+        fs::write(root.join(HELPERS_DIR_NAME).join("twice.rhai"), "params[0] * 2").unwrap();
+        fs::write(sub.join(HELPERS_DIR_NAME).join("twice.rhai"), "params[0] * 3").unwrap();
+        fs::write(sub.join(HELPERS_DIR_NAME).join("broken.rhai"), "let = ;").unwrap();
+
+        let page = sub.join("page.hbs");
+        fs::write(&page, "{{shout \"hi\"}} {{twice 2}}").unwrap();
+
+        let registry = TemplateRegistry::new();
+        let mut sources = convention_sources(&[sub.as_path(), root], "");
+        sources.push(TemplateSource::template("page", &page));
+
+        let hbs = registry.with_sources(&sources).unwrap();
+        assert_eq!("HI 6", hbs.render("page", &()).unwrap());
+
+        // A broken script only fails the templates that use it
+        let err = hbs.render_template("{{broken}}", &()).unwrap_err();
+        assert!(err.to_string().contains("failed to compile"));
+    }
+
+    #[test]
+    fn index_sources_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+
+        let registry = TemplateRegistry::new();
+        let dirs = [sub.as_path(), root];
+
+        let hbs = registry.with_sources(&index_sources(&dirs, "_index.hbs")).unwrap();
+        assert!(hbs.render("index", &()).unwrap().contains("<table"));
+
+        fs::write(root.join("_index.hbs"), "{{#each children}}{{name}}{{/each}}").unwrap();
+        let hbs = registry.with_sources(&index_sources(&dirs, "_index.hbs")).unwrap();
+        assert_eq!("a", hbs.render("index", &json!({ "children": [{ "name": "a" }] })).unwrap());
+    }
+}
\ No newline at end of file
@@ -1,56 +1,44 @@
-use std::sync::Arc;
+use std::path::Path;
 
-use async_std::fs;
-use mime;
 use roa::{Context, Next, Result, status, http};
-use handlebars::Handlebars;
 
-use crate::{Resource, ServerConfig};
-use super::{url_encode_helper, url_decode_helper};
-
-const INDEX_TEMPLATE: &'static str = include_str!("../../../include/templates/index.html.hbs");
-const LAYOUT_TEMPLATE: &'static str = include_str!("../../../include/templates/layout.html.hbs");
-
-pub async fn render_hbs<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
-    let mut hbs = Handlebars::new();
-
-    hbs.register_helper("url_encode", Box::new(url_encode_helper));
-    hbs.register_helper("url_decode", Box::new(url_decode_helper));
-
-    let res = ctx.load::<Resource>("res");
-
-    if let Some(_res) = res.as_deref() {
-        //let ServerConfig { ref index_template, ref layout_template .. } = res.config().server;
-
-        // TODO: look for files based on convention
-        // TODO: Template inheritance
-        let _ = hbs.register_template_string("index", INDEX_TEMPLATE);
-        let _ = hbs.register_partial("layout", LAYOUT_TEMPLATE);
-    } else {
-        let _ = hbs.register_template_string("index", INDEX_TEMPLATE);
-        let _ = hbs.register_partial("layout", LAYOUT_TEMPLATE);
-    }
-
-    let hbs = Arc::new(hbs);
-
-    ctx.store("hbs", Arc::clone(&hbs));
+use crate::{PolyState, Resource, ServerConfig};
+use crate::middleware::{etag_from_content, cache_privately};
+use super::{TemplateSource, convention_sources, RequestContext, PageContext};
 
+pub async fn render_hbs(ctx: &mut Context<PolyState>, next: Next<'_>) -> Result {
     next.await?;
     
     let res = ctx.load::<Resource>("res");
 
     if let Some(res) = res.as_deref() {
-        let ServerConfig { ref render_hbs, .. } = res.config().server;
+        let ServerConfig { ref render_hbs, ref layout, .. } = res.config().server;
 
         if *render_hbs {
-            if let Some(ext) = res.fs_path().extension().as_deref() {
+            if let Some(ext) = res.fs_path().extension() {
                 if ext == "hbs" {
-                    let tpl = fs::read_to_string(res.fs_path()).await?;
-    
-                    let html = hbs.render_template(tpl.as_str(), res.context())?;
+                    let dirs: Vec<&Path> = res.ancestors()
+                        .into_iter()
+                        .filter(|path| path.is_dir())
+                        .collect();
+
+                    let name = res.fs_path().to_string_lossy();
+                    let mut sources = convention_sources(&dirs, layout.as_str());
+
+                    sources.push(TemplateSource::template(name.as_ref(), res.fs_path()));
+
+                    let hbs = ctx.templates().with_sources(&sources)?;
+                    let context = PageContext {
+                        resource: res.context(),
+                        request: &RequestContext::from_context(ctx),
+                    };
+
+                    let html = hbs.render(name.as_ref(), &context)?;
     
                     ctx.resp.headers.insert("Content-Length", html.len().into());
                     ctx.resp.headers.insert("Content-Type", mime::TEXT_HTML_UTF_8.as_ref().parse()?);
+                    ctx.resp.headers.insert("ETag", etag_from_content(html.as_bytes()).parse()?);
+                    cache_privately(&mut ctx.resp.headers)?;
     
                     ctx.resp.write(html);
 
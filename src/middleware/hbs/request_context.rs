@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use roa::Context;
+use serde::Serialize;
+
+use crate::PolyState;
+use crate::resource::ResourceContext;
+
+/// Request headers templates may read, by lowercase name.
+const EXPOSED_HEADERS: &[&str] = &[
+    "accept",
+    "accept-language",
+    "dnt",
+    "referer",
+    "user-agent",
+];
+
+/// What templates can see of the current request, as `request.*`.
+#[derive(Debug, Serialize)]
+pub struct RequestContext {
+    method: String,
+    scheme: &'static str,
+    host: Option<String>,
+    path: String,
+    query_string: Option<String>,
+    query: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    cookies: BTreeMap<String, String>,
+    remote_addr: String,
+}
+
+impl RequestContext {
+    pub fn from_context(ctx: &Context<PolyState>) -> Self {
+        let headers = EXPOSED_HEADERS
+            .iter()
+            .filter_map(|&name| Some((name.to_owned(), ctx.get(name)?.to_owned())))
+            .collect();
+
+        let cookies = ctx.req.headers
+            .get_all("Cookie")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+
+                Some((name.to_owned(), decode_component(value.trim_matches('"'))))
+            })
+            .collect();
+
+        let host = ctx.get("Host")
+            .map(str::to_owned)
+            .or_else(|| ctx.uri().authority().map(|authority| authority.to_string()));
+
+        Self {
+            method: ctx.method().to_string(),
+            scheme: match ctx.is_tls() {
+                true => "https",
+                false => "http",
+            },
+            host,
+            path: ctx.uri().path().to_owned(),
+            query_string: ctx.uri().query().map(str::to_owned),
+            query: parse_query(ctx.uri().query().unwrap_or_default()),
+            headers,
+            cookies,
+            remote_addr: ctx.remote_addr.to_string(),
+        }
+    }
+}
+
+/// The context templates are rendered with: the resource, plus the request.
+#[derive(Serialize)]
+pub struct PageContext<'a> {
+    #[serde(flatten)]
+    pub resource: &'a ResourceContext,
+    pub request: &'a RequestContext,
+}
+
+/// Decodes a query string into a map, where later parameters win. Both `&` and
+/// `;` separate parameters.
+pub fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split(['&', ';'])
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (decode_component(name), decode_component(value)),
+            None => (decode_component(pair), String::new()),
+        })
+        .collect()
+}
+
+fn decode_component(value: &str) -> String {
+    let value = value.replace('+', " ");
+
+    String::from_utf8_lossy(urlencoding::decode_binary(value.as_bytes()).as_ref()).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_query_test() {
+        let query = parse_query("q=hello+world&lang=en;C=M;O=D&empty&q2=%C3%A9%2B&bad=%ff");
+
+        assert_eq!("hello world", query["q"]);
+        assert_eq!("en", query["lang"]);
+        assert_eq!("M", query["C"]);
+        assert_eq!("D", query["O"]);
+        assert_eq!("", query["empty"]);
+        assert_eq!("é+", query["q2"]);
+        assert_eq!("\u{fffd}", query["bad"]);
+        assert!(parse_query("").is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use handlebars::{Handlebars, Helper, Context, RenderContext, Output, RenderError, HelperDef};
+use rhai::{Dynamic, Engine};
+use rhai::module_resolvers::DummyModuleResolver;
+
+/// Script helpers are stopped after this many operations...
+const MAX_SCRIPT_OPERATIONS: u64 = 100_000;
+/// ...or after running for this long, whichever comes first.
+const MAX_SCRIPT_DURATION: Duration = Duration::from_millis(100);
+
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+thread_local! {
+    static SCRIPT_STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// A rhai engine for script helpers, which can't import modules or touch the
+/// filesystem, and is stopped when it exceeds its limits.
+pub fn script_engine() -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+
+    // Each script runs synchronously on one thread, starting from operation 1
+    engine.on_progress(|operations| {
+        SCRIPT_STARTED.with(|started| {
+            let now = Instant::now();
+
+            let started_at = match (operations, started.get()) {
+                (1, _) | (_, None) => {
+                    started.set(Some(now));
+
+                    now
+                },
+                (_, Some(started_at)) => started_at,
+            };
+
+            match now.duration_since(started_at) > MAX_SCRIPT_DURATION {
+                true => Some(Dynamic::from(format!("script helper ran for more than {:?}", MAX_SCRIPT_DURATION))),
+                false => None,
+            }
+        })
+    });
+
+    engine.on_print(|text| log::info!("[script] {}", text));
+    engine.on_debug(|text, _, pos| log::debug!("[script] {:?} {}", pos, text));
+
+    engine
+}
+
+/// Stands in for a script helper that failed to compile, so only the templates
+/// that use it fail to render.
+pub struct ScriptErrorHelper {
+    pub name: String,
+    pub message: String,
+}
+
+impl HelperDef for ScriptErrorHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        _: &mut dyn Output,
+    ) -> Result<(), RenderError> {
+        Err(RenderError::new(format!("Script helper `{}` failed to compile: {}", self.name, self.message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn registry(name: &str, script: &str) -> Handlebars<'static> {
+        let mut hbs = Handlebars::new();
+        hbs.set_engine(script_engine());
+        hbs.register_script_helper(name, script).unwrap();
+
+        hbs
+    }
+
+    #[test]
+    fn script_helper_test() {
+        let hbs = registry("greet", r#"let name = params[0]; "hello " + name + hash["suffix"]"#);
+
+        let rendered = hbs.render_template(r#"{{greet name suffix="!"}}"#, &json!({ "name": "world" })).unwrap();
+        assert_eq!("hello world!", rendered);
+    }
+
+    #[test]
+    fn script_limits_test() {
+        let hbs = registry("spin", "loop { }");
+        assert!(hbs.render_template("{{spin}}", &()).is_err());
+
+        let hbs = registry("recurse", "fn f(x) { f(x + 1) } f(0)");
+        assert!(hbs.render_template("{{recurse}}", &()).is_err());
+
+        let hbs = registry("load", r#"import "secrets" as s; 1"#);
+        assert!(hbs.render_template("{{load}}", &()).is_err());
+    }
+}
\ No newline at end of file
@@ -4,22 +4,31 @@ pub async fn logger<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     log::info!("--> {} {}", ctx.method(), ctx.uri().path());
 
     let result = next.await;
+
+    // Middleware may store the reason for an error response
+    let reason = match ctx.load::<String>("error") {
+        Some(reason) => format!(" ({})", reason.as_str()),
+        None => String::new(),
+    };
+
     match result {
         Err(ref status) if status.status_code.is_server_error() => {
             log::error!(
-                "<-- {} {} {}",
+                "<-- {} {} {}{}",
                 ctx.method(),
                 ctx.uri().path(),
                 status.status_code,
+                reason,
             );
         },
 
         Err(ref status) => {
             log::warn!(
-                "<-- {} {} {}",
+                "<-- {} {} {}{}",
                 ctx.method(),
                 ctx.uri().path(),
                 status.status_code,
+                reason,
             );
         },
 
@@ -1,23 +1,87 @@
 mod logger;
 mod early_return;
+mod error_page;
 mod server_header;
+mod conditional_get;
+mod byte_range;
+mod compress;
 mod allow_methods;
 mod trailing_slash;
 mod serve_file;
 mod resolve_file;
 mod use_index;
 mod hbs;
+mod render_markdown;
 mod auto_index;
 mod resolve_resource;
 
 pub use logger::logger;
 pub use early_return::early_return;
+pub use error_page::error_page;
 pub use server_header::server_header;
+pub use conditional_get::conditional_get;
+pub use compress::compress;
 pub use allow_methods::allow_methods;
 pub use trailing_slash::trailing_slash;
 pub use serve_file::serve_file;
 pub use resolve_file::resolve_file;
 pub use use_index::use_index;
-pub use hbs::render_hbs;
+pub use hbs::{render_hbs, TemplateRegistry};
+pub use render_markdown::render_markdown;
+use hbs::{convention_sources, index_sources, error_sources, RequestContext, parse_query};
+pub use hbs::{LAYOUT_FILE_NAME, LAYOUTS_DIR_NAME, PARTIALS_DIR_NAME, HELPERS_DIR_NAME};
 pub use auto_index::auto_index;
-pub use resolve_resource::resolve_resource;
\ No newline at end of file
+pub use resolve_resource::resolve_resource;
+
+use roa::{Context, Result, Status, status};
+use roa::http::HeaderMap;
+
+use crate::resource::ResourceError;
+
+use hbs::render_config_errors;
+use conditional_get::{etag_from_meta, etag_from_content, etag_strong_eq};
+use byte_range::{parse_range, ByteRange};
+use compress::{select_encoding, vary_on, Encoding};
+use render_markdown::untrusted_markdown_to_html;
+
+/// Converts a `ResourceError` into an error status, keeping the reason for `logger`.
+fn resource_error<S>(ctx: &mut Context<S>, err: ResourceError) -> Status {
+    let status = status!(err.status_code());
+
+    ctx.store("error", err.to_string());
+
+    status
+}
+
+/// Parses every value of a header like `Accept` or `Accept-Encoding` into its
+/// items, lowercased, and their q-values, which default to 1.
+fn parse_quality_list(headers: &HeaderMap, name: &str) -> Vec<(String, f32)> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let value = parts.next().unwrap_or_default().to_ascii_lowercase();
+
+            let qvalue = parts
+                .filter_map(|param| param.split_once('='))
+                .filter(|(name, _)| name.trim_end().eq_ignore_ascii_case("q"))
+                .find_map(|(_, q)| q.trim_start().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            (value, qvalue)
+        })
+        .collect()
+}
+
+/// Keeps shared caches from storing a page rendered with request details, such
+/// as cookies, which may differ from one user to the next.
+fn cache_privately(headers: &mut HeaderMap) -> Result {
+    headers.insert("Cache-Control", "private".parse()?);
+
+    Ok(())
+}
\ No newline at end of file
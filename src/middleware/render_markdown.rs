@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use async_std::fs;
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use roa::{Context, Next, Result, status, http};
+use serde::Serialize;
+
+use crate::{PolyState, Resource, ServerConfig};
+use crate::resource::ResourceContext;
+use crate::front_matter::split_front_matter;
+use super::{etag_from_content, cache_privately, convention_sources, RequestContext};
+
+#[derive(Serialize)]
+struct MarkdownContext<'a> {
+    #[serde(flatten)]
+    resource: &'a ResourceContext,
+    request: RequestContext,
+    title: Option<String>,
+    content: String,
+}
+
+pub async fn render_markdown(ctx: &mut Context<PolyState>, next: Next<'_>) -> Result {
+    next.await?;
+
+    let res = ctx.load::<Resource>("res");
+
+    if let Some(res) = res.as_deref() {
+        let ServerConfig { ref render_markdown, ref layout, .. } = res.config().server;
+
+        let is_markdown = res.fs_path()
+            .extension()
+            .is_some_and(|ext| ext == "md" || ext == "markdown");
+
+        if *render_markdown && is_markdown && res.fs_path().is_file() {
+            let source = fs::read_to_string(res.fs_path()).await?;
+
+            let (page, body) = match split_front_matter(source.as_str()) {
+                Some((page, len)) => (Some(page), &source[len..]),
+                None => (None, source.as_str()),
+            };
+
+            let (content, heading) = markdown_to_html(body);
+
+            let dirs: Vec<&Path> = res.ancestors()
+                .into_iter()
+                .filter(|path| path.is_dir())
+                .collect();
+
+            let sources = convention_sources(&dirs, layout.as_str());
+            let hbs = ctx.templates().with_sources(&sources)?;
+
+            let page_title = page
+                .as_ref()
+                .and_then(|page| page.get("title"))
+                .and_then(|title| title.as_str())
+                .map(str::to_owned);
+
+            let title = page_title.or(heading).or_else(|| {
+                res.fs_path()
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            });
+
+            let context = MarkdownContext {
+                resource: res.context(),
+                request: RequestContext::from_context(ctx),
+                title,
+                content,
+            };
+
+            let html = hbs.render("markdown", &context)?;
+
+            ctx.resp.headers.insert("Content-Length", html.len().into());
+            ctx.resp.headers.insert("Content-Type", mime::TEXT_HTML_UTF_8.as_ref().parse()?);
+            ctx.resp.headers.insert("ETag", etag_from_content(html.as_bytes()).parse()?);
+            cache_privately(&mut ctx.resp.headers)?;
+
+            ctx.resp.write(html);
+
+            return Err(status!(http::StatusCode::OK));
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders CommonMark with GitHub extensions, giving every heading an anchor.
+///
+/// Also returns the text of the first top-level heading, if any.
+pub fn markdown_to_html(source: &str) -> (String, Option<String>) {
+    let (events, title) = markdown_events(source);
+
+    let mut content = String::new();
+    html::push_html(&mut content, events.into_iter());
+
+    (content, title)
+}
+
+/// Like `markdown_to_html`, for text that may come from the request rather than
+/// the web root. Raw HTML is escaped, and `javascript:` links lead nowhere.
+pub fn untrusted_markdown_to_html(source: &str) -> String {
+    let (events, _) = markdown_events(source);
+
+    let events = events.into_iter().map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if is_script_url(&dest_url) => {
+            Event::Start(Tag::Link { link_type, dest_url: CowStr::Borrowed("#"), title, id })
+        },
+        event => event,
+    });
+
+    let mut content = String::new();
+    html::push_html(&mut content, events);
+
+    content
+}
+
+fn is_script_url(url: &str) -> bool {
+    url.trim_start()
+        .get(..11)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("javascript:"))
+}
+
+fn markdown_events(source: &str) -> (Vec<Event<'_>>, Option<String>) {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_GFM;
+
+    let mut events: Vec<Event> = Parser::new_ext(source, options).collect();
+    let mut used_ids = HashSet::new();
+    let mut title = None;
+
+    for index in 0..events.len() {
+        let level = match events[index] {
+            Event::Start(Tag::Heading { level, .. }) => level,
+            _ => continue,
+        };
+
+        let text: String = events[index + 1..]
+            .iter()
+            .take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_))))
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        if level == HeadingLevel::H1 && title.is_none() {
+            title = Some(text.to_owned());
+        }
+
+        if let Event::Start(Tag::Heading { ref mut id, .. }) = events[index] {
+            match id {
+                Some(id) => {
+                    used_ids.insert(id.to_string());
+                },
+                None => {
+                    let anchor = unique_anchor(slugify(text.as_str()), &mut used_ids);
+
+                    *id = Some(CowStr::from(anchor));
+                },
+            }
+        }
+    }
+
+    (events, title)
+}
+
+fn slugify(text: &str) -> String {
+    let slug = text
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace() || *c == '-' || *c == '_')
+        .map(|c| if c.is_whitespace() { '-' } else { c })
+        .collect::<String>();
+
+    match slug.is_empty() {
+        true => "section".to_owned(),
+        false => slug,
+    }
+}
+
+fn unique_anchor(slug: String, used_ids: &mut HashSet<String>) -> String {
+    let mut anchor = slug.to_owned();
+    let mut suffix = 0;
+
+    while used_ids.contains(&anchor) {
+        suffix += 1;
+        anchor = format!("{}-{}", slug, suffix);
+    }
+
+    used_ids.insert(anchor.to_owned());
+
+    anchor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heading_anchors_test() {
+        let (html, title) = markdown_to_html("# Hello, World!\n\n## Usage\n\n## Usage\n\n## Custom {#mine}\n");
+
+        assert_eq!(Some("Hello, World!".to_owned()), title);
+        assert!(html.contains("<h1 id=\"hello-world\">Hello, World!</h1>"));
+        assert!(html.contains("<h2 id=\"usage\">Usage</h2>"));
+        assert!(html.contains("<h2 id=\"usage-1\">Usage</h2>"));
+        assert!(html.contains("<h2 id=\"mine\">Custom</h2>"));
+    }
+
+    #[test]
+    fn gfm_extensions_test() {
+        let (html, title) = markdown_to_html("| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n\n~~old~~ text[^1]\n\n[^1]: note\n");
+
+        assert_eq!(None, title);
+        assert!(html.contains("<table>"));
+        assert!(html.contains("checkbox"));
+        assert!(html.contains("<del>old</del>"));
+        assert!(html.contains("footnote"));
+    }
+}
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use roa::{Context, Next, Result};
 
 use crate::{Resource, ServerConfig};
+use super::resource_error;
 
 pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     let res = ctx.load::<Resource>("res");
@@ -11,7 +12,7 @@ pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
         let ServerConfig { ref allow_extension_elision, .. } = res.config().server;
 
         // Search for file with supported extensions
-        if allow_extension_elision.len() > 0 {
+        if !allow_extension_elision.is_empty() {
             let mut candidates = allow_extension_elision
                 .iter()
                 .map(|ext| {
@@ -28,7 +29,7 @@ pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
                     file_path
                 })
                 .filter_map(|path| {
-                    if path.is_file() {
+                    if path.is_file() && !res.hides(path.as_path()) {
                         // TODO: return Resources
                         Some(path)
                     } else {
@@ -37,7 +38,7 @@ pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
                 })
                 .collect::<Vec<PathBuf>>();
             
-            if candidates.len() > 0 {
+            if !candidates.is_empty() {
                 candidates.sort();
 
                 let file_path = candidates.remove(0);
@@ -46,7 +47,6 @@ pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
                     .unwrap()
                     .to_string_lossy();
                 
-                // TODO: Move into Resource resolve
                 let mut uri_path = ctx.uri().path().to_owned();
                 let boundary = uri_path.rfind('/').unwrap();
 
@@ -56,7 +56,10 @@ pub async fn resolve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
 
                 ctx.resp.headers.insert("Content-Location", location.parse()?);
 
-                let res = Resource::new(location.as_str(), res.root_path());
+                let res = match res.resolve(location.as_str()) {
+                    Ok(res) => res,
+                    Err(err) => return Err(resource_error(ctx, err)),
+                };
 
                 ctx.store("res", res);
 
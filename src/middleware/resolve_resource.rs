@@ -1,9 +1,43 @@
 use roa::{Context, Result, Next};
 
 use crate::{PolyState, Resource};
+use crate::resource::ResourceError;
+use super::{resource_error, render_config_errors};
 
 pub async fn resolve_resource(ctx: &mut Context<PolyState>, next: Next<'_>) -> Result {
-    let res = Resource::new(ctx.uri().path(), ctx.root_path());
+    let res = match Resource::new(ctx.uri().path(), ctx.root_path(), ctx.config_cache()) {
+        Ok(res) => res,
+        Err(err) => {
+            if let ResourceError::Config(ref errors) = err {
+                errors
+                    .iter()
+                    .filter(|err| ctx.first_report(err))
+                    .for_each(|err| log::error!("{}", err));
+
+                if ctx.dev_mode() {
+                    let html = render_config_errors(&ctx.templates().base(), ctx.uri().path(), errors)?;
+
+                    ctx.resp.headers.insert("Content-Length", html.len().into());
+                    ctx.resp.headers.insert("Content-Type", mime::TEXT_HTML_UTF_8.as_ref().parse()?);
+
+                    ctx.resp.write(html);
+
+                    // Tells error_page the body is already written
+                    ctx.store("diagnostics", true);
+                }
+            }
+
+            return Err(resource_error(ctx, err));
+        },
+    };
+
+    let stats = ctx.config_cache().stats();
+
+    log::debug!("Config cache: {}", stats);
+
+    if ctx.dev_mode() {
+        ctx.resp.headers.insert("X-Config-Cache", stats.to_string().parse()?);
+    }
     
     ctx.store("res", res);
 
@@ -1,9 +1,17 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
 use async_std::fs::File;
+use async_std::io::prelude::*;
 use roa::{Context, Next, Result, status, http};
-use mime;
-use mime_guess;
 
-use crate::Resource;
+use crate::{Resource, ServerConfig};
+use crate::front_matter::read_front_matter;
+use super::{etag_from_meta, etag_strong_eq, parse_range, ByteRange};
+use super::{select_encoding, vary_on, Encoding};
 
 pub async fn serve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     next.await?;
@@ -11,24 +19,171 @@ pub async fn serve_file<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     let res = ctx.load::<Resource>("res");
 
     if let Some(res) = res.as_deref() {
-        if ctx.method() == &http::Method::GET && res.fs_path().is_file() {
+        let is_get_or_head = *ctx.method() == http::Method::GET || *ctx.method() == http::Method::HEAD;
+
+        if is_get_or_head && res.fs_path().is_file() {
             let mime_type = mime_guess::from_path(res.fs_path())
                 .first()
                 .unwrap_or(mime::TEXT_PLAIN_UTF_8);
-            
-            let file = File::open(res.fs_path()).await?;
+
+            let ServerConfig { ref serve_precompressed, .. } = res.config().server;
+
+            // Front matter is metadata for listings, not part of the page
+            let is_html = mime_type.type_() == mime::TEXT && mime_type.subtype() == mime::HTML;
+
+            let offset = match is_html {
+                true => read_front_matter(res.fs_path()).map_or(0, |(_, len)| len as u64),
+                false => 0,
+            };
+
+            let mut file_path = res.fs_path().to_owned();
+
+            // A sibling would still contain the front matter, so such pages are never precompressed
+            if *serve_precompressed && offset == 0 {
+                let available: Vec<Encoding> = Encoding::ALL
+                    .iter()
+                    .copied()
+                    .filter(|encoding| precompressed_path(res, *encoding).is_some())
+                    .collect();
+
+                if !available.is_empty() {
+                    vary_on(&mut ctx.resp.headers, "Accept-Encoding")?;
+                }
+
+                if let Some(encoding) = select_encoding(&ctx.req.headers, &available) {
+                    if let Some(path) = precompressed_path(res, encoding) {
+                        file_path = path;
+
+                        ctx.resp.headers.insert("Content-Encoding", encoding.as_str().parse()?);
+                    }
+                }
+            }
+
+            let mut file = File::open(&file_path).await?;
             let metadata = file.metadata().await?;
-            
-            ctx.resp.headers.insert("Content-Length", metadata.len().into());
-            ctx.resp.headers.insert("Content-Type", mime_type.as_ref().parse()?);
-                
-            ctx.resp.write_reader(file);
+            let len = metadata.len().saturating_sub(offset);
+            let etag = etag_from_meta(&metadata);
+            let last_modified = metadata.modified().ok().map(httpdate::fmt_http_date);
+
+            ctx.resp.headers.insert("Accept-Ranges", "bytes".parse()?);
+            ctx.resp.headers.insert("ETag", etag.parse()?);
+
+            if let Some(ref last_modified) = last_modified {
+                ctx.resp.headers.insert("Last-Modified", last_modified.parse()?);
+            }
+
+            // A failed If-Range validation means the client's partial copy is stale
+            let if_range_ok = match ctx.get("If-Range") {
+                Some(if_range) if if_range.starts_with('"') || if_range.starts_with("W/") => {
+                    etag_strong_eq(if_range, etag.as_str())
+                },
+                Some(if_range) => Some(if_range) == last_modified.as_deref(),
+                None => true,
+            };
+
+            let ranges = match (ctx.get("Range"), if_range_ok) {
+                (Some(range), true) => parse_range(range, len),
+                _ => None,
+            };
+
+            match ranges {
+                Some(ranges) if ranges.is_empty() => {
+                    let content_range = format!("bytes */{}", len);
+                    ctx.resp.headers.insert("Content-Range", content_range.parse()?);
+
+                    return Err(status!(http::StatusCode::RANGE_NOT_SATISFIABLE));
+                },
+
+                Some(ranges) if ranges.len() == 1 => {
+                    let range = ranges[0];
+                    let file = open_range(&file_path, offset, range).await?;
+
+                    ctx.resp.headers.insert("Content-Length", range.len().into());
+                    ctx.resp.headers.insert("Content-Type", mime_type.as_ref().parse()?);
+                    ctx.resp.headers.insert("Content-Range", range.content_range(len).parse()?);
+
+                    ctx.resp.write_reader(file);
+
+                    return Err(status!(http::StatusCode::PARTIAL_CONTENT));
+                },
+
+                Some(ranges) => {
+                    let boundary = multipart_boundary(etag.as_str());
+                    let mut content_length = 0;
 
-            return Err(status!(http::StatusCode::OK));
+                    for range in ranges.iter() {
+                        let part_header = format!(
+                            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                            boundary,
+                            mime_type,
+                            range.content_range(len),
+                        );
+
+                        content_length += part_header.len() as u64 + range.len();
+
+                        ctx.resp.write(part_header);
+                        ctx.resp.write_reader(open_range(&file_path, offset, *range).await?);
+                    }
+
+                    let closing = format!("\r\n--{}--\r\n", boundary);
+                    content_length += closing.len() as u64;
+
+                    ctx.resp.write(closing);
+
+                    let content_type = format!("multipart/byteranges; boundary={}", boundary);
+
+                    ctx.resp.headers.insert("Content-Length", content_length.into());
+                    ctx.resp.headers.insert("Content-Type", content_type.parse()?);
+
+                    return Err(status!(http::StatusCode::PARTIAL_CONTENT));
+                },
+
+                None => {
+                    if offset > 0 {
+                        file.seek(SeekFrom::Start(offset)).await?;
+                    }
+
+                    ctx.resp.headers.insert("Content-Length", len.into());
+                    ctx.resp.headers.insert("Content-Type", mime_type.as_ref().parse()?);
+
+                    ctx.resp.write_reader(file);
+
+                    return Err(status!(http::StatusCode::OK));
+                },
+            }
         }
     }
 
-    ctx.resp.headers.insert("Content-Type", mime::TEXT_PLAIN_UTF_8.as_ref().parse()?);
-
     Err(status!(http::StatusCode::NOT_FOUND))
-}
\ No newline at end of file
+}
+
+/// Path of an existing sibling of the resource precompressed with `encoding`,
+/// e.g. `app.js.br`, if the path and symlink policies would serve it.
+fn precompressed_path(res: &Resource, encoding: Encoding) -> Option<PathBuf> {
+    let extension = encoding.extension()?;
+
+    let mut file_name = res.fs_path().file_name()?.to_owned();
+    file_name.push(format!(".{}", extension));
+
+    let sibling = res.fs_path().with_file_name(file_name);
+
+    match sibling.is_file() && res.permits(sibling.as_path()) {
+        true => Some(sibling),
+        false => None,
+    }
+}
+
+async fn open_range(path: &Path, offset: u64, range: ByteRange) -> Result<async_std::io::Take<File>> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(offset + range.start)).await?;
+
+    Ok(file.take(range.len()))
+}
+
+fn multipart_boundary(etag: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    etag.hash(&mut hasher);
+    SystemTime::now().hash(&mut hasher);
+
+    format!("polyserve-{:016x}", hasher.finish())
+}
@@ -3,24 +3,32 @@ use std::path::PathBuf;
 use roa::{Context, Next, Result};
 
 use crate::{Resource, ServerConfig};
+use super::resource_error;
+
+const README_FILE_NAME: &str = "README.md";
 
 pub async fn use_index<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
     let res = ctx.load::<Resource>("res");
 
     if let Some(res) = res.as_deref() {
-        let ServerConfig { ref use_index, ref allow_extension_elision, .. } = res.config().server;
+        let ServerConfig {
+            ref use_index,
+            ref allow_extension_elision,
+            ref render_markdown,
+            ..
+        } = res.config().server;
 
-        if *use_index == false {
+        if !*use_index {
             return next.await;
         }
 
         // Search for index file with supported extensions
-        if res.fs_path().is_dir() && allow_extension_elision.len() > 0 {
+        if res.fs_path().is_dir() {
             let mut candidates = allow_extension_elision
                 .iter()
                 .map(|ext| res.fs_path().to_owned().join(format!("index.{}", ext)))
                 .filter_map(|path| {
-                    if path.is_file() {
+                    if path.is_file() && !res.hides(path.as_path()) {
                         // TODO: Wrap in newtype implementing Comparison
                         Some(path)
                     } else {
@@ -29,20 +37,32 @@ pub async fn use_index<S>(ctx: &mut Context<S>, next: Next<'_>) -> Result {
                 })
                 .collect::<Vec<PathBuf>>();
             
-            if candidates.len() > 0 {
-                candidates.sort();
+            candidates.sort();
+
+            // A README is only an index if nothing more specific exists
+            if *render_markdown {
+                let readme_path = res.fs_path().join(README_FILE_NAME);
+
+                if readme_path.is_file() && !res.hides(readme_path.as_path()) {
+                    candidates.push(readme_path);
+                }
+            }
 
+            if !candidates.is_empty() {
                 let index_path = candidates.remove(0);
                 let file_name = index_path
                     .file_name()
                     .unwrap()
                     .to_string_lossy();
 
-                let location = format!("{}/{}", ctx.uri().path(), file_name);
+                let location = format!("{}/{}", ctx.uri().path().trim_end_matches('/'), file_name);
 
                 ctx.resp.headers.insert("Content-Location", location.parse()?);
                 
-                let res = Resource::new(location.as_str(), res.root_path());
+                let res = match res.resolve(location.as_str()) {
+                    Ok(res) => res,
+                    Err(err) => return Err(resource_error(ctx, err)),
+                };
 
                 ctx.store("res", res);
 
@@ -0,0 +1,251 @@
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::ServerConfig;
+use crate::request_config::{SymlinkPolicy, CONFIG_FILE_NAME};
+use crate::resource::ResourceError;
+use crate::middleware::{LAYOUT_FILE_NAME, LAYOUTS_DIR_NAME, PARTIALS_DIR_NAME, HELPERS_DIR_NAME};
+use crate::site_data::{DATA_TOML_FILE_NAME, DATA_JSON_FILE_NAME};
+
+/// Directory under the web root holding the TLS certificate and private key.
+pub const IDENTITY_DIR_NAME: &str = ".identity";
+
+/// Files and directories templates are built from, which are hidden wherever
+/// they appear unless allowed by `allow_paths`.
+const TEMPLATE_NAMES: &[&str] = &[
+    LAYOUT_FILE_NAME,
+    LAYOUTS_DIR_NAME,
+    PARTIALS_DIR_NAME,
+    HELPERS_DIR_NAME,
+    DATA_TOML_FILE_NAME,
+    DATA_JSON_FILE_NAME,
+];
+
+/// Decides which paths under the web root are never served or listed.
+#[derive(Debug, Clone)]
+pub struct PathPolicy {
+    hide_dotfiles: bool,
+    deny: GlobSet,
+    allow: GlobSet,
+    symlinks: SymlinkPolicy,
+    index_template: String,
+    error_templates: GlobSet,
+}
+
+impl PathPolicy {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            hide_dotfiles: config.hide_dotfiles,
+            deny: build_glob_set(&config.deny_paths),
+            allow: build_glob_set(&config.allow_paths),
+            symlinks: config.symlinks,
+            index_template: config.index_template.to_owned(),
+            error_templates: build_glob_set(&config.error_templates
+                .iter()
+                .map(|name| name.replace("{status}", "[0-9][0-9][0-9]").replace("{class}", "[0-9]"))
+                .collect::<Vec<String>>()),
+        }
+    }
+
+    /// Checks that `path` under the canonical `root` only passes through symlinks
+    /// permitted by the symlink policy.
+    ///
+    /// Returns the canonical path, which for a missing path is that of its nearest
+    /// existing ancestor joined with the rest.
+    pub fn check_symlinks(&self, root: &Path, path: &Path) -> Result<PathBuf, ResourceError> {
+        let canonical = match self.symlinks {
+            SymlinkPolicy::Follow => canonicalize_existing(path).unwrap_or_else(|| path.to_owned()),
+
+            SymlinkPolicy::FollowWithinRoot => {
+                let canonical = canonicalize_existing(path)
+                    .ok_or_else(|| ResourceError::OutsideRoot(path.to_owned()))?;
+
+                match canonical.starts_with(root) {
+                    true => canonical,
+                    false => return Err(ResourceError::OutsideRoot(canonical)),
+                }
+            },
+
+            SymlinkPolicy::Deny => {
+                let symlink = path
+                    .ancestors()
+                    .take_while(|&path| path != root)
+                    .find(|path| is_symlink(path));
+
+                match symlink {
+                    Some(symlink) => return Err(ResourceError::SymlinkDenied(symlink.to_owned())),
+                    None => path.to_owned(),
+                }
+            },
+        };
+
+        Ok(canonical)
+    }
+
+    /// Like `check_symlinks`, but only for the last component of `path`, whose
+    /// parent is assumed to be checked already.
+    pub fn permits_entry(&self, root: &Path, path: &Path) -> bool {
+        if !is_symlink(path) {
+            return true;
+        }
+
+        let canonical = match self.symlinks {
+            SymlinkPolicy::Deny => return false,
+            _ => path.canonicalize(),
+        };
+
+        match canonical {
+            Ok(canonical) => match canonical.strip_prefix(root) {
+                Ok(rel_path) => !self.is_hidden(rel_path),
+                Err(_) => self.symlinks == SymlinkPolicy::Follow,
+            },
+            // Broken links only lead somewhere once followed anywhere
+            Err(_) => self.symlinks == SymlinkPolicy::Follow,
+        }
+    }
+
+    /// Checks a path relative to the web root. A path is hidden if any of its
+    /// ancestors is hidden.
+    pub fn is_hidden(&self, rel_path: &Path) -> bool {
+        let mut prefix = PathBuf::new();
+
+        for component in rel_path.components() {
+            let name = match component {
+                Component::Normal(name) => name,
+                _ => continue,
+            };
+
+            prefix.push(name);
+
+            // Configuration and keys are hidden regardless of allow rules
+            if name == CONFIG_FILE_NAME || prefix == Path::new(IDENTITY_DIR_NAME) {
+                return true;
+            }
+
+            if (self.is_template(name) || self.is_denied(&prefix, name)) && !self.is_allowed(&prefix, name) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn is_template(&self, name: &OsStr) -> bool {
+        TEMPLATE_NAMES.iter().any(|&template| name == template)
+            || (!self.index_template.is_empty() && name == self.index_template.as_str())
+            || self.error_templates.is_match(name)
+    }
+
+    fn is_denied(&self, prefix: &Path, name: &OsStr) -> bool {
+        let is_dotfile = name.to_string_lossy().starts_with('.');
+
+        (self.hide_dotfiles && is_dotfile) || self.deny.is_match(prefix) || self.deny.is_match(name)
+    }
+
+    fn is_allowed(&self, prefix: &Path, name: &OsStr) -> bool {
+        self.allow.is_match(prefix) || self.allow.is_match(name)
+    }
+}
+
+/// Canonicalizes the nearest existing ancestor of `path` and joins the rest back on.
+fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+    let existing = path.ancestors().find(|path| path.exists())?;
+    let canonical = existing.canonicalize().ok()?;
+
+    match path.strip_prefix(existing) {
+        Ok(rest) if rest.as_os_str().is_empty() => Some(canonical),
+        Ok(rest) => Some(canonical.join(rest)),
+        Err(_) => Some(canonical),
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata()
+        .is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+fn build_glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+            .literal_separator(true)
+            .build();
+
+        match glob {
+            Ok(glob) => {
+                builder.add(glob);
+            },
+            Err(err) => log::warn!("Ignoring invalid path glob {:?}: {}", pattern, err),
+        }
+    }
+
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(deny: &[&str], allow: &[&str]) -> PathPolicy {
+        let config = ServerConfig {
+            hide_dotfiles: true,
+            index_template: "_index.hbs".to_owned(),
+            error_templates: vec!["_{status}.hbs".to_owned(), "_{class}xx.hbs".to_owned(), "_error.hbs".to_owned()],
+            deny_paths: deny.iter().map(|&glob| glob.to_owned()).collect(),
+            allow_paths: allow.iter().map(|&glob| glob.to_owned()).collect(),
+            ..ServerConfig::default()
+        };
+
+        PathPolicy::from_config(&config)
+    }
+
+    #[test]
+    fn hidden_by_default_test() {
+        let policy = policy(&[], &[]);
+
+        assert!(policy.is_hidden(Path::new(".config.toml")));
+        assert!(policy.is_hidden(Path::new("sub/.config.toml")));
+        assert!(policy.is_hidden(Path::new(".identity/server.key")));
+        assert!(policy.is_hidden(Path::new(".git/HEAD")));
+        assert!(policy.is_hidden(Path::new("sub/_layout.hbs")));
+        assert!(policy.is_hidden(Path::new("_partials/nav.hbs")));
+        assert!(policy.is_hidden(Path::new("_layouts/blog.hbs")));
+        assert!(policy.is_hidden(Path::new("_helpers/shout.rhai")));
+        assert!(policy.is_hidden(Path::new("sub/_index.hbs")));
+        assert!(policy.is_hidden(Path::new("sub/_data.toml")));
+        assert!(policy.is_hidden(Path::new("sub/_404.hbs")));
+        assert!(policy.is_hidden(Path::new("_5xx.hbs")));
+        assert!(policy.is_hidden(Path::new("_error.hbs")));
+        assert!(!policy.is_hidden(Path::new("_4044.hbs")));
+        assert!(!policy.is_hidden(Path::new("")));
+        assert!(!policy.is_hidden(Path::new("sub/index.html")));
+    }
+
+    #[test]
+    fn deny_and_allow_paths_test() {
+        let policy = policy(&["*.bak", "/private/**"], &[".well-known", "/.config.toml", ".identity"]);
+
+        assert!(policy.is_hidden(Path::new("sub/notes.bak")));
+        assert!(policy.is_hidden(Path::new("private/secret.txt")));
+        assert!(!policy.is_hidden(Path::new("sub/private/notes.txt")));
+        assert!(!policy.is_hidden(Path::new(".well-known/security.txt")));
+
+        // Allow rules never expose configuration or keys
+        assert!(policy.is_hidden(Path::new(".config.toml")));
+        assert!(policy.is_hidden(Path::new(".identity/server.key")));
+    }
+
+    #[test]
+    fn allowed_template_names_test() {
+        let policy = policy(&[], &["_data.json", "/_layouts", "/_404.hbs"]);
+
+        assert!(!policy.is_hidden(Path::new("sub/_data.json")));
+        assert!(!policy.is_hidden(Path::new("_layouts/blog.hbs")));
+        assert!(!policy.is_hidden(Path::new("_404.hbs")));
+        assert!(policy.is_hidden(Path::new("sub/_data.toml")));
+        assert!(policy.is_hidden(Path::new("_partials/nav.hbs")));
+    }
+}
@@ -1,10 +1,21 @@
+use std::collections::HashSet;
+use std::fmt::Display;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::ConfigCache;
+use crate::middleware::TemplateRegistry;
 
 #[derive(Debug, Clone)]
 pub struct PolyState {
     pub(crate) addr: SocketAddr,
     pub(crate) root: PathBuf,
+    pub(crate) tls: bool,
+    pub(crate) dev_mode: bool,
+    pub(crate) reported_errors: Arc<Mutex<HashSet<String>>>,
+    pub(crate) config_cache: ConfigCache,
+    pub(crate) templates: TemplateRegistry,
 }
 
 impl PolyState {
@@ -16,4 +27,29 @@ impl PolyState {
     pub fn root_path(&self) -> &Path {
         self.root.as_path()
     }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    pub fn dev_mode(&self) -> bool {
+        self.dev_mode
+    }
+
+    pub fn config_cache(&self) -> &ConfigCache {
+        &self.config_cache
+    }
+
+    pub fn templates(&self) -> &TemplateRegistry {
+        &self.templates
+    }
+
+    /// Returns `true` only the first time an error is reported, so persistent
+    /// problems are logged once instead of on every request.
+    pub fn first_report(&self, err: &impl Display) -> bool {
+        match self.reported_errors.lock() {
+            Ok(mut reported) => reported.insert(err.to_string()),
+            Err(_) => true,
+        }
+    }
 }
\ No newline at end of file
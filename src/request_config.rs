@@ -1,43 +1,270 @@
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use serde::Deserialize;
-use config::{Config, File, FileFormat};
+use serde::{Serialize, Deserialize};
+use toml::{Table, Value};
 
-const DEFAULT_CONFIG: &'static str = include_str!("../include/default.toml");
+const DEFAULT_CONFIG: &str = include_str!("../include/default.toml");
+
+pub const CONFIG_FILE_NAME: &str = ".config.toml";
+
+/// A problem with a configuration file, located as precisely as possible.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigError {
+    pub path: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    /// Locates an error within `contents` by its span, if it has one.
+    fn from_toml(path: &Path, contents: &str, key: Option<String>, err: &toml::de::Error) -> Self {
+        let line = err.span()
+            .and_then(|span| contents.get(..span.start))
+            .map(|before| before.matches('\n').count() + 1);
+
+        Self {
+            path: Some(path.to_owned()),
+            line,
+            key,
+            message: err.message().to_owned(),
+        }
+    }
+
+    fn from_message(path: Option<&Path>, message: impl ToString) -> Self {
+        Self {
+            path: path.map(Path::to_owned),
+            line: None,
+            key: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "{}", path.display())?,
+            None => write!(f, "(merged configuration)")?,
+        }
+
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+
+        if let Some(ref key) = self.key {
+            write!(f, " `{}`", key)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
 
-// TODO: Remove config-rs dependency
 #[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct RequestConfig {
     pub server: ServerConfig,
 }
 
 impl RequestConfig {
-    pub fn generate_from_ancestors(ancestors: &Vec<&Path>) -> Self {
-        let mut cfg = Config::default();
-
-        let _ = cfg.merge(File::from_str(DEFAULT_CONFIG, FileFormat::Toml));
+    /// Cascades the config files of `ancestors`, ordered deepest first, so the
+    /// nearest file wins.
+    pub fn generate_from_ancestors(ancestors: &[&Path]) -> Result<Self, Vec<ConfigError>> {
+        let mut merged = default_table().clone();
+        let mut errors = Vec::new();
 
         ancestors
             .iter()
-            .map(|path| path.join(".config.toml"))
+            .rev()
+            .map(|path| path.join(CONFIG_FILE_NAME))
             .filter(|path| path.is_file())
-            .for_each(|config_path| {
-                let _ = cfg.merge(File::from(config_path.as_path()));
+            .for_each(|config_path| match Self::read_file(config_path.as_path()) {
+                Ok(table) => merge_tables(&mut merged, table),
+                Err(err) => errors.push(err),
             });
 
-        let config = cfg.try_into()
-            .unwrap_or(Self::default());
-        
-        config
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Value::Table(merged)
+            .try_into()
+            .map_err(|err: toml::de::Error| vec![ConfigError::from_message(None, err.message())])
+    }
+
+    /// Checks a single configuration file for syntax errors, mistyped values and
+    /// unknown keys.
+    pub fn validate_file(path: &Path) -> Result<(), ConfigError> {
+        Self::read_file(path).map(|_| ())
+    }
+
+    /// Reads and validates a single configuration file, returning its table to
+    /// be merged.
+    fn read_file(path: &Path) -> Result<Table, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| ConfigError::from_message(Some(path), err))?;
+
+        let table = contents.parse::<Table>()
+            .map_err(|err| ConfigError::from_toml(path, contents.as_str(), None, &err))?;
+
+        // The parsed table is checked as is, and only parsed again to locate an error
+        match Value::Table(table.clone()).try_into::<Self>() {
+            Ok(_) => Ok(table),
+            Err(err) => Err(Self::locate_error(path, contents.as_str())
+                .unwrap_or_else(|| ConfigError::from_message(Some(path), err.message()))),
+        }
+    }
+
+    /// Deserializes `contents` while tracking the path to the offending key.
+    fn locate_error(path: &Path, contents: &str) -> Option<ConfigError> {
+        let err = serde_path_to_error::deserialize::<_, Self>(toml::Deserializer::new(contents)).err()?;
+
+        let key = match err.path().to_string() {
+            key if key == "." => None,
+            key => Some(key),
+        };
+
+        Some(ConfigError::from_toml(path, contents, key, err.inner()))
+    }
+
+    /// Validates every configuration file under `root`, without following symlinks.
+    pub fn validate_tree(root: &Path) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let mut dirs = vec![root.to_owned()];
+
+        while let Some(dir) = dirs.pop() {
+            let config_path = dir.join(CONFIG_FILE_NAME);
+
+            if config_path.is_file() {
+                if let Err(err) = Self::validate_file(config_path.as_path()) {
+                    errors.push(err);
+                }
+            }
+
+            if let Ok(read_dir) = dir.read_dir() {
+                dirs.extend(read_dir
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+                    .map(|entry| entry.path()));
+            }
+        }
+
+        errors
+    }
+}
+
+/// The built-in defaults, which every cascade starts from.
+fn default_table() -> &'static Table {
+    static DEFAULTS: OnceLock<Table> = OnceLock::new();
+
+    // The built-in defaults are known to parse
+    DEFAULTS.get_or_init(|| DEFAULT_CONFIG.parse::<Table>().unwrap())
+}
+
+/// Deep-merges `other` into `base`, where values from `other` win. Arrays are
+/// replaced, not concatenated.
+fn merge_tables(base: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (base.get_mut(key.as_str()), value) {
+            (Some(Value::Table(base)), Value::Table(other)) => merge_tables(base, other),
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
     }
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ServerConfig {
     pub force_trailing_slash: bool,
     pub allow_extension_elision: Vec<String>,
     pub use_index: bool,
     pub auto_index: bool,
+    pub index_template: String,
+    pub max_listing_entries: usize,
+    pub dirs_first: bool,
+    pub error_templates: Vec<String>,
     pub allow_methods: Vec<String>,
     pub render_hbs: bool,
+    pub layout: String,
+    pub render_markdown: bool,
+    pub compress: bool,
+    pub compress_min_length: u64,
+    pub compress_types: Vec<String>,
+    pub compress_level: CompressLevel,
+    pub serve_precompressed: bool,
+    pub hide_dotfiles: bool,
+    pub deny_paths: Vec<String>,
+    pub allow_paths: Vec<String>,
+    pub symlinks: SymlinkPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    Follow,
+    #[default]
+    FollowWithinRoot,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressLevel {
+    Fastest,
+    #[default]
+    Default,
+    Best,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_config_wins_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+
+        fs::create_dir(&sub).unwrap();
+        fs::write(root.join(CONFIG_FILE_NAME), "[server]\nuse_index = false\nlayout = \"root\"\n").unwrap();
+        fs::write(sub.join(CONFIG_FILE_NAME), "[server]\nlayout = \"sub\"\n").unwrap();
+
+        let config = RequestConfig::generate_from_ancestors(&[sub.as_path(), root]).unwrap();
+        assert_eq!("sub", config.server.layout);
+        assert!(!config.server.use_index);
+
+        let config = RequestConfig::generate_from_ancestors(&[root]).unwrap();
+        assert_eq!("root", config.server.layout);
+        assert!(config.server.auto_index);
+    }
+
+    #[test]
+    fn validate_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+
+        fs::write(&path, "[server]\nuse_index = true\n").unwrap();
+        assert!(RequestConfig::validate_file(&path).is_ok());
+
+        fs::write(&path, "[server]\n\nuse_index = \"yes\"\n").unwrap();
+        let err = RequestConfig::validate_file(&path).unwrap_err();
+        assert_eq!(Some(3), err.line);
+        assert_eq!(Some("server.use_index"), err.key.as_deref());
+
+        fs::write(&path, "[server]\nauto_indx = true\n").unwrap();
+        let err = RequestConfig::validate_file(&path).unwrap_err();
+        assert_eq!(Some(2), err.line);
+        assert!(err.message.contains("auto_indx"));
+
+        fs::write(&path, "[server\n").unwrap();
+        let err = RequestConfig::validate_file(&path).unwrap_err();
+        assert_eq!(Some(1), err.line);
+        assert_eq!(None, err.key);
+    }
 }
\ No newline at end of file
@@ -1,21 +1,84 @@
+use std::fmt;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::string::FromUtf8Error;
+use std::sync::{Arc, OnceLock};
 
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use globset::GlobMatcher;
+use roa::http::StatusCode;
 
-use crate::RequestConfig;
+use crate::{ConfigCache, RequestConfig, ServerConfig, PathPolicy};
+use crate::request_config::ConfigError;
+use crate::front_matter::read_front_matter;
+use crate::site_data::load_cascaded_data;
 
-pub const UNKNOWN: &'static str = "(unknown)";
+pub const UNKNOWN: &str = "(unknown)";
+
+#[derive(Debug)]
+pub enum ResourceError {
+    /// The request path is not valid UTF-8 once percent-decoded.
+    InvalidEncoding(FromUtf8Error),
+    /// The request path can't be safely mapped onto the filesystem.
+    InvalidPath(String),
+    /// The path resolves to a location outside of the web root.
+    OutsideRoot(PathBuf),
+    /// The path passes through a symlink, which the symlink policy denies.
+    SymlinkDenied(PathBuf),
+    /// The path is hidden by the cascaded `PathPolicy`.
+    Hidden(PathBuf),
+    /// A configuration file along the path is invalid.
+    Config(Vec<ConfigError>),
+}
+
+impl ResourceError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ResourceError::InvalidEncoding(_) => StatusCode::BAD_REQUEST,
+            ResourceError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+            ResourceError::OutsideRoot(_) => StatusCode::FORBIDDEN,
+            ResourceError::SymlinkDenied(_) => StatusCode::FORBIDDEN,
+            ResourceError::Hidden(_) => StatusCode::NOT_FOUND,
+            ResourceError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::InvalidEncoding(err) => write!(f, "Malformed percent-encoding in request path: {}", err),
+            ResourceError::InvalidPath(reason) => write!(f, "Invalid request path: {}", reason),
+            ResourceError::OutsideRoot(path) => write!(f, "Path {:?} is outside of the web root", path),
+            ResourceError::SymlinkDenied(path) => write!(f, "Symlink {:?} is not allowed", path),
+            ResourceError::Hidden(path) => write!(f, "Path {:?} is hidden", path),
+            ResourceError::Config(errors) => match errors.as_slice() {
+                [err] => write!(f, "Invalid configuration: {}", err),
+                [err, rest @ ..] => write!(f, "Invalid configuration: {} (and {} more)", err, rest.len()),
+                [] => write!(f, "Invalid configuration"),
+            },
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+impl From<FromUtf8Error> for ResourceError {
+    fn from(err: FromUtf8Error) -> Self {
+        ResourceError::InvalidEncoding(err)
+    }
+}
 
 #[derive(Debug)]
 pub struct Resource {
     root_path: PathBuf,
-    uri_path: String,
     fs_path: PathBuf,
     ancestors: Vec<PathBuf>,
-    config: RequestConfig,
-    context: ResourceContext,
+    config: Arc<RequestConfig>,
+    context: OnceLock<ResourceContext>,
+    policy: PathPolicy,
+    configs: ConfigCache,
 }
 
 impl Resource {
@@ -38,21 +101,48 @@ impl Resource {
         &self.config
     }
 
+    /// Template context for this resource, only read from the filesystem when
+    /// first needed.
     pub fn context(&self) -> &ResourceContext {
-        &self.context
+        self.context.get_or_init(|| self.load_context(None))
+    }
+
+    /// Like `context`, but only listing children whose name matches `filter`.
+    /// The filter applies before `max_listing_entries`, so it searches the whole
+    /// directory.
+    pub fn filtered_context(&self, filter: &GlobMatcher) -> ResourceContext {
+        self.load_context(Some(filter))
+    }
+
+    /// Checks a filesystem path against this resource's cascaded path policy.
+    pub fn hides(&self, path: &Path) -> bool {
+        match path.strip_prefix(self.root_path()) {
+            Ok(rel_path) => self.policy.is_hidden(rel_path),
+            Err(_) => true,
+        }
     }
 
-    pub fn new(uri_path: &str, root_path: &Path) -> Self {
+    /// Checks a filesystem path whose parent is already permitted, e.g. a
+    /// directory entry, against both the path and the symlink policies.
+    pub fn permits(&self, path: &Path) -> bool {
+        !self.hides(path) && self.policy.permits_entry(self.root_path(), path)
+    }
+
+    /// Resolves another URI path under the same web root, e.g. an index file.
+    pub fn resolve(&self, uri_path: &str) -> Result<Self, ResourceError> {
+        Self::new(uri_path, self.root_path(), &self.configs)
+    }
+
+    /// Maps a URI path onto the filesystem under `root_path`, which must be canonical.
+    pub fn new(uri_path: &str, root_path: &Path, configs: &ConfigCache) -> Result<Self, ResourceError> {
         let root_path = root_path.to_owned();
-        let uri_path = uri_path.to_owned();
 
         // FS Path
-        let fs_path = root_path.to_owned();
-        let fs_path = urlencoding::decode(uri_path.as_str())
-            .unwrap()
-            .split("/")
-            .skip(1)
-            .fold(fs_path, |path, segment| path.join(segment));
+        let decoded = urlencoding::decode(uri_path)?;
+
+        let fs_path = normalize_segments(decoded.as_ref())?
+            .iter()
+            .fold(root_path.to_owned(), |path, segment| path.join(segment));
         
         // Ancestors
         let ancestors: Vec<&Path> = fs_path
@@ -61,71 +151,259 @@ impl Resource {
             .collect();
         
         // Request Config
-        let config = RequestConfig::generate_from_ancestors(&ancestors);
+        let config = configs.get(&ancestors)
+            .map_err(ResourceError::Config)?;
 
-        let context = {
-            let name = if let Some(name) = fs_path.file_name() {
-                name.to_owned().into_string().ok()
-            } else {
-                None
-            };
-    
-            let metadata = match fs_path.metadata() {
-                Ok(ref meta) => Some(ResourceMetadata::from_meta(meta)),
-                Err(_) => None,
-            };
-    
-            let ancestors: Vec<ResourceContext> = ancestors
-                .iter()
-                .map(|path| ResourceContext::from_path(path, true))
-                .collect();
-            
-            let children = match fs_path.is_dir() {
-                true => {
-                    if let Ok (read_dir) = fs_path.read_dir() {
-                        Some(read_dir
-                            .filter_map(|entry| entry.ok())
-                            .map(|ref entry| ResourceContext::from_dir_entry(entry))
-                            .collect())
-                    } else {
-                        None
-                    }
-                },
-                false => None
-            };
-    
-            ResourceContext {
-                name,
-                metadata,
-                children, 
-    
-                ancestors: Some(ancestors),
-            }
+        // Hidden Paths
+        let policy = PathPolicy::from_config(&config.server);
+        let is_hidden = match fs_path.strip_prefix(root_path.as_path()) {
+            Ok(rel_path) => policy.is_hidden(rel_path),
+            Err(_) => true,
+        };
+
+        if is_hidden {
+            return Err(ResourceError::Hidden(fs_path));
+        }
+
+        // Symlinks may still point anywhere, so check where the path really leads
+        let canonical = policy.check_symlinks(root_path.as_path(), fs_path.as_path())?;
+
+        // Nor may they reveal a hidden path, e.g. `keys -> .identity`
+        let is_hidden = match canonical.strip_prefix(root_path.as_path()) {
+            Ok(rel_path) => policy.is_hidden(rel_path),
+            Err(_) => false,
         };
 
+        if is_hidden {
+            return Err(ResourceError::Hidden(fs_path));
+        }
+
         let ancestors: Vec<PathBuf> = ancestors.iter().map(|&path| path.to_owned()).collect();
         
-        Self {
+        Ok(Self {
             root_path,
-            uri_path,
             fs_path,
             ancestors,
             config,
-            context,
+            context: OnceLock::new(),
+            policy,
+            configs: configs.clone(),
+        })
+    }
+
+    fn load_context(&self, filter: Option<&GlobMatcher>) -> ResourceContext {
+        let ServerConfig { ref max_listing_entries, .. } = self.config.server;
+
+        let name = if let Some(name) = self.fs_path.file_name() {
+            name.to_owned().into_string().ok()
+        } else {
+            None
+        };
+
+        let metadata = match self.fs_path.metadata() {
+            Ok(ref meta) => Some(ResourceMetadata::from_meta(meta)),
+            Err(_) => None,
+        };
+
+        // Root first, ending with the resource itself, for breadcrumbs
+        let ancestors: Vec<ResourceContext> = self.ancestors
+            .iter()
+            .rev()
+            .map(|path| {
+                let mut ancestor = ResourceContext::from_path(path, true);
+
+                ancestor.uri = self.uri_of(path, path.is_dir());
+                ancestor.display_name = self.display_name_of(path);
+
+                ancestor
+            })
+            .collect();
+
+        let uri = self.uri_of(self.fs_path(), self.fs_path.is_dir());
+
+        let parent_uri = match self.fs_path.parent() {
+            Some(parent) if self.fs_path != self.root_path => self.uri_of(parent, true),
+            _ => None,
+        };
+
+        let mut truncated = false;
+
+        let children = match self.fs_path.is_dir() {
+            true => {
+                if let Ok (read_dir) = self.fs_path.read_dir() {
+                    let mut entries = read_dir
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| filter.is_none_or(|filter| filter.is_match(entry.file_name())))
+                        .filter(|entry| self.permits(entry.path().as_path()));
+
+                    // Only entries that are listed pay for a metadata lookup
+                    let children: Vec<ResourceContext> = entries
+                        .by_ref()
+                        .take(match *max_listing_entries {
+                            0 => usize::MAX,
+                            max => max,
+                        })
+                        .map(|ref entry| {
+                            let mut child = ResourceContext::from_dir_entry(entry);
+                            let is_dir = child.metadata().is_some_and(|meta| meta.is_dir());
+
+                            child.uri = self.uri_of(entry.path().as_path(), is_dir);
+
+                            child
+                        })
+                        .collect();
+
+                    // Finding one more entry is enough, however large the directory
+                    truncated = entries.next().is_some();
+
+                    Some(children)
+                } else {
+                    None
+                }
+            },
+            false => None
+        };
+
+        let page = read_front_matter(self.fs_path()).map(|(page, _)| page);
+
+        let dirs: Vec<&Path> = self.ancestors
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|path| path.is_dir())
+            .collect();
+
+        let data = load_cascaded_data(&dirs);
+
+        ResourceContext {
+            name,
+            display_name: self.display_name_of(self.fs_path()),
+            uri,
+            parent_uri,
+            metadata,
+            children,
+            truncated,
+            page,
+            data: Some(data),
+
+            ancestors: Some(ancestors),
+        }
+    }
+
+    /// The URI path of a filesystem path under the web root, percent-encoded,
+    /// with a trailing slash for directories.
+    fn uri_of(&self, path: &Path, is_dir: bool) -> Option<String> {
+        let rel_path = path.strip_prefix(self.root_path()).ok()?;
+        let mut uri = String::new();
+
+        for component in rel_path.components() {
+            uri.push('/');
+            uri.push_str(urlencoding::encode(component.as_os_str().to_str()?).as_ref());
+        }
+
+        if is_dir || uri.is_empty() {
+            uri.push('/');
+        }
+
+        Some(uri)
+    }
+
+    /// The name to show for a path, where the web root is `/`.
+    fn display_name_of(&self, path: &Path) -> Option<String> {
+        match path == self.root_path() {
+            true => Some("/".to_owned()),
+            false => path.file_name()?.to_str().map(str::to_owned),
+        }
+    }
+}
+
+/// Splits a decoded URI path into filesystem path segments, collapsing `.` and `..`.
+fn normalize_segments(decoded: &str) -> Result<Vec<&str>, ResourceError> {
+    if decoded.contains('\0') {
+        return Err(ResourceError::InvalidPath("NUL byte".to_owned()));
+    }
+
+    if decoded.contains('\\') {
+        return Err(ResourceError::InvalidPath("backslash".to_owned()));
+    }
+
+    let mut segments = Vec::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(ResourceError::InvalidPath("traverses above the web root".to_owned()));
+                }
+            },
+
+            // Anything else must stay a single plain component (e.g. no drive prefixes)
+            segment => match Path::new(segment).components().collect::<Vec<_>>().as_slice() {
+                [Component::Normal(_)] => segments.push(segment),
+                _ => return Err(ResourceError::InvalidPath(format!("segment {:?}", segment))),
+            },
         }
     }
+
+    Ok(segments)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceContext {
     name: Option<String>,
+    /// Name for breadcrumbs and headings, which is `/` for the web root.
+    display_name: Option<String>,
+    /// Percent-encoded URI path, ending in `/` for directories.
+    uri: Option<String>,
+    /// URI path of the parent directory, except for the web root.
+    parent_uri: Option<String>,
     metadata: Option<ResourceMetadata>,
     children: Option<Vec<Self>>,
-
+    /// Whether `max_listing_entries` left out some children.
+    #[serde(default)]
+    truncated: bool,
+    /// Front matter of a page.
+    page: Option<serde_json::Value>,
+    /// Data cascaded from `_data.toml` and `_data.json` files.
+    data: Option<serde_json::Value>,
+    /// From the web root to this resource, inclusive.
     ancestors: Option<Vec<Self>>,
 }
 
 impl ResourceContext {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn uri(&self) -> Option<&str> {
+        self.uri.as_deref()
+    }
+
+    pub fn parent_uri(&self) -> Option<&str> {
+        self.parent_uri.as_deref()
+    }
+
+    pub fn metadata(&self) -> Option<&ResourceMetadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn children(&self) -> Option<&[Self]> {
+        self.children.as_deref()
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn page(&self) -> Option<&serde_json::Value> {
+        self.page.as_ref()
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.children.as_mut()
+    }
+
     pub fn from_path(path: &Path, shallow: bool) -> Self {
         let name = if let Some(name) = path.file_name() {
             name.to_owned().into_string().ok()
@@ -141,9 +419,15 @@ impl ResourceContext {
         if shallow {
             Self {
                 name,
+                display_name: None,
+                uri: None,
+                parent_uri: None,
                 metadata,
                 ancestors: None,
                 children: None, 
+                truncated: false,
+                page: None,
+                data: None,
             }
         } else {
             let children = match path.is_dir() {
@@ -162,34 +446,66 @@ impl ResourceContext {
 
             Self {
                 name,
+                display_name: None,
+                uri: None,
+                parent_uri: None,
                 metadata,
                 ancestors: None,
                 children, 
+                truncated: false,
+                page: None,
+                data: None,
             }
         }
     }
 
     pub fn from_dir_entry(entry: &fs::DirEntry) -> Self {
         let name = entry.file_name().into_string().ok();
-        
-        let metadata = match entry.metadata() {
-            Ok(ref meta) => Some(ResourceMetadata::from_meta(meta)),
+        let path = entry.path();
+
+        // Follow symlinks so linked directories are listed as directories
+        let metadata = match path.metadata().or_else(|_| entry.metadata()) {
+            Ok(ref meta) => {
+                let mut metadata = ResourceMetadata::from_meta(meta);
+
+                if entry.file_type().is_ok_and(|file_type| file_type.is_symlink()) {
+                    metadata.is_symlink = true;
+                    metadata.symlink_target = fs::read_link(&path)
+                        .ok()
+                        .map(|target| target.to_string_lossy().into_owned());
+                }
+
+                Some(metadata)
+            },
             Err(_) => None,
         };
 
+        let page = match path.is_file() {
+            true => read_front_matter(path.as_path()).map(|(page, _)| page),
+            false => None,
+        };
+
         Self {
             name,
+            display_name: None,
+            uri: None,
+            parent_uri: None,
             metadata,
             ancestors: None,
             children: None,
+            truncated: false,
+            page,
+            data: None,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceMetadata {
     is_dir: bool,
     is_file: bool,
+    is_symlink: bool,
+    symlink_target: Option<String>,
     len: u64,
     readonly: bool,
     modified: String,
@@ -198,10 +514,24 @@ pub struct ResourceMetadata {
 }
 
 impl ResourceMetadata {
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+
+    /// RFC 3339 modification time, or `UNKNOWN`.
+    pub fn modified(&self) -> &str {
+        self.modified.as_str()
+    }
+
     pub fn from_meta(meta: &fs::Metadata) -> Self {
         let modified = match meta.modified() {
             Ok(st) => {
-                let dt: DateTime<Utc> = st.clone().into();
+                let dt: DateTime<Utc> = st.into();
 
                 format!("{}", dt.format("%+"))
             },
@@ -210,7 +540,7 @@ impl ResourceMetadata {
 
         let accessed = match meta.accessed() {
             Ok(st) => {
-                let dt: DateTime<Utc> = st.clone().into();
+                let dt: DateTime<Utc> = st.into();
 
                 format!("{}", dt.format("%+"))
             },
@@ -219,7 +549,7 @@ impl ResourceMetadata {
 
         let created = match meta.created() {
             Ok(st) => {
-                let dt: DateTime<Utc> = st.clone().into();
+                let dt: DateTime<Utc> = st.into();
 
                 format!("{}", dt.format("%+"))
             },
@@ -229,6 +559,8 @@ impl ResourceMetadata {
         Self {
             is_dir: meta.is_dir(),
             is_file: meta.is_file(),
+            is_symlink: meta.file_type().is_symlink(),
+            symlink_target: None,
             len: meta.len(),
             readonly: meta.permissions().readonly(),
             modified,
@@ -236,4 +568,262 @@ impl ResourceMetadata {
             created,
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn web_root() -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub").join("a.txt"), "a").unwrap();
+
+        (dir, root)
+    }
+
+    fn error_status(uri_path: &str, root: &Path) -> Option<StatusCode> {
+        Resource::new(uri_path, root, &ConfigCache::default()).err().map(|err| err.status_code())
+    }
+
+    #[test]
+    fn normalize_segments_test() {
+        assert_eq!(vec!["sub", "a.txt"], normalize_segments("/sub/./a.txt").unwrap());
+        assert_eq!(vec!["a.txt"], normalize_segments("/sub/../a.txt").unwrap());
+        assert_eq!(vec!["sub"], normalize_segments("//sub//").unwrap());
+        assert!(normalize_segments("/").unwrap().is_empty());
+
+        assert!(normalize_segments("/..").is_err());
+        assert!(normalize_segments("/sub/../../a.txt").is_err());
+        assert!(normalize_segments("/sub\\..\\..\\a.txt").is_err());
+        assert!(normalize_segments("/a.txt\0.png").is_err());
+    }
+
+    #[test]
+    fn malformed_encoding_test() {
+        let (_dir, root) = web_root();
+
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/%ff", &root));
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/sub/%c3%28.txt", &root));
+        assert_eq!(None, error_status("/sub/%c3%a9.txt", &root));
+    }
+
+    #[test]
+    fn encoded_traversal_test() {
+        let (_dir, root) = web_root();
+
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/%2e%2e/etc/passwd", &root));
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/%2E%2E%2F%2E%2E%2Fetc%2Fpasswd", &root));
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/sub/..%2f..%2fetc/passwd", &root));
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/..%5c..%5cetc%5cpasswd", &root));
+        assert_eq!(Some(StatusCode::BAD_REQUEST), error_status("/sub/a.txt%00.html", &root));
+
+        // Collapsed segments that stay inside the root are fine
+        let res = Resource::new("/sub/%2e%2e/sub/./a.txt", &root, &ConfigCache::default()).unwrap();
+        assert_eq!(root.join("sub").join("a.txt"), res.fs_path());
+
+        // Double encoding only decodes once, leaving a literal file name
+        let res = Resource::new("/%252e%252e/etc", &root, &ConfigCache::default()).unwrap();
+        assert_eq!(root.join("%2e%2e").join("etc"), res.fs_path());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_outside_root_test() {
+        let (_dir, root) = web_root();
+        let (_outside_dir, outside) = web_root();
+
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inside")).unwrap();
+
+        assert_eq!(Some(StatusCode::FORBIDDEN), error_status("/escape/sub/a.txt", &root));
+        assert_eq!(Some(StatusCode::FORBIDDEN), error_status("/escape/missing.txt", &root));
+        assert_eq!(None, error_status("/inside/a.txt", &root));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_policy_test() {
+        let (_dir, root) = web_root();
+        let (_outside_dir, outside) = web_root();
+
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inside")).unwrap();
+
+        fs::write(root.join(".config.toml"), "[server]\nsymlinks = \"deny\"").unwrap();
+        assert_eq!(Some(StatusCode::FORBIDDEN), error_status("/inside/a.txt", &root));
+        assert_eq!(None, error_status("/sub/a.txt", &root));
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let listing = serde_json::to_value(res.context()).unwrap();
+        assert_eq!(1, listing["children"].as_array().unwrap().len());
+
+        fs::write(root.join(".config.toml"), "[server]\nsymlinks = \"follow\"").unwrap();
+        assert_eq!(None, error_status("/escape/sub/a.txt", &root));
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let listing = serde_json::to_value(res.context()).unwrap();
+        let escape = listing["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|child| child["name"] == "escape")
+            .unwrap();
+        assert_eq!(true, escape["metadata"]["is_symlink"]);
+        assert_eq!(true, escape["metadata"]["is_dir"]);
+        assert_eq!(outside.to_str().unwrap(), escape["metadata"]["symlink_target"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_to_hidden_path_test() {
+        let (_dir, root) = web_root();
+
+        fs::create_dir(root.join(".identity")).unwrap();
+        fs::write(root.join(".identity").join("server.key"), "key").unwrap();
+        fs::create_dir(root.join("_partials")).unwrap();
+        fs::write(root.join("_partials").join("nav.hbs"), "nav").unwrap();
+        fs::write(root.join(".config.toml"), "[server]\nmax_listing_entries = 0").unwrap();
+
+        std::os::unix::fs::symlink(root.join(".identity"), root.join("keys")).unwrap();
+        std::os::unix::fs::symlink(root.join("_partials"), root.join("sub").join("parts")).unwrap();
+        std::os::unix::fs::symlink(root.join(".config.toml"), root.join("config.txt")).unwrap();
+
+        assert_eq!(Some(StatusCode::NOT_FOUND), error_status("/keys/server.key", &root));
+        assert_eq!(Some(StatusCode::NOT_FOUND), error_status("/keys/", &root));
+        assert_eq!(Some(StatusCode::NOT_FOUND), error_status("/sub/parts/nav.hbs", &root));
+        assert_eq!(Some(StatusCode::NOT_FOUND), error_status("/config.txt", &root));
+        assert_eq!(None, error_status("/sub/a.txt", &root));
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let listing = serde_json::to_value(res.context()).unwrap();
+        let names: Vec<&str> = listing["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|child| child["name"].as_str())
+            .collect();
+        assert_eq!(vec!["sub"], names);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permits_sibling_test() {
+        let (_dir, root) = web_root();
+        let (_outside_dir, outside) = web_root();
+        let sub = root.join("sub");
+
+        fs::write(sub.join("a.txt.gz"), "gz").unwrap();
+        std::os::unix::fs::symlink(outside.join("sub").join("a.txt"), sub.join("a.txt.br")).unwrap();
+        fs::write(root.join(".config.toml"), "[server]\ndeny_paths = [\"*.gz\"]").unwrap();
+
+        let res = Resource::new("/sub/a.txt", &root, &ConfigCache::default()).unwrap();
+        assert!(res.permits(sub.join("a.txt").as_path()));
+        assert!(!res.permits(sub.join("a.txt.gz").as_path()));
+        assert!(!res.permits(sub.join("a.txt.br").as_path()));
+    }
+
+    #[test]
+    fn lazy_capped_listing_test() {
+        let (_dir, root) = web_root();
+
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("c.txt"), "c").unwrap();
+        fs::write(root.join(".config.toml"), "[server]\nmax_listing_entries = 2").unwrap();
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        assert!(res.context.get().is_none());
+
+        let listing = serde_json::to_value(res.context()).unwrap();
+        assert_eq!(2, listing["children"].as_array().unwrap().len());
+        assert_eq!(true, listing["truncated"]);
+
+        fs::write(root.join(".config.toml"), "[server]\nmax_listing_entries = 3").unwrap();
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let listing = serde_json::to_value(res.context()).unwrap();
+        assert_eq!(3, listing["children"].as_array().unwrap().len());
+        assert_eq!(false, listing["truncated"]);
+    }
+
+    #[test]
+    fn filtered_capped_listing_test() {
+        let (_dir, root) = web_root();
+
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("c.log"), "c").unwrap();
+        fs::write(root.join(".config.toml"), "[server]\nmax_listing_entries = 1").unwrap();
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let filter = globset::Glob::new("*.log").unwrap().compile_matcher();
+
+        let listing = serde_json::to_value(res.filtered_context(&filter)).unwrap();
+        assert_eq!("c.log", listing["children"][0]["name"]);
+        assert_eq!(1, listing["children"].as_array().unwrap().len());
+        assert_eq!(false, listing["truncated"]);
+
+        // The cached context stays unfiltered
+        assert_eq!(true, serde_json::to_value(res.context()).unwrap()["truncated"]);
+    }
+
+    #[test]
+    fn ancestor_uris_test() {
+        let (_dir, root) = web_root();
+
+        fs::create_dir(root.join("sub").join("a dir")).unwrap();
+
+        let res = Resource::new("/sub/a%20dir/", &root, &ConfigCache::default()).unwrap();
+        let context = serde_json::to_value(res.context()).unwrap();
+
+        let crumbs: Vec<(&str, &str)> = context["ancestors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|ancestor| (ancestor["display_name"].as_str().unwrap(), ancestor["uri"].as_str().unwrap()))
+            .collect();
+
+        assert_eq!(vec![("/", "/"), ("sub", "/sub/"), ("a dir", "/sub/a%20dir/")], crumbs);
+        assert_eq!("/sub/a%20dir/", context["uri"]);
+        assert_eq!("/sub/", context["parent_uri"]);
+
+        let res = Resource::new("/", &root, &ConfigCache::default()).unwrap();
+        let context = serde_json::to_value(res.context()).unwrap();
+        assert_eq!("/", context["uri"]);
+        assert_eq!(serde_json::Value::Null, context["parent_uri"]);
+
+        let sub = context["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|child| child["name"] == "sub")
+            .unwrap();
+        assert_eq!("/sub/", sub["uri"]);
+    }
+
+    #[test]
+    fn front_matter_context_test() {
+        let (_dir, root) = web_root();
+
+        fs::write(root.join("sub").join("post.md"), "---\ntitle: Post\n---\nBody").unwrap();
+
+        let res = Resource::new("/sub/post.md", &root, &ConfigCache::default()).unwrap();
+        let context = serde_json::to_value(res.context()).unwrap();
+        assert_eq!("Post", context["page"]["title"]);
+
+        let res = Resource::new("/sub/", &root, &ConfigCache::default()).unwrap();
+        let listing = serde_json::to_value(res.context()).unwrap();
+        let post = listing["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|child| child["name"] == "post.md")
+            .unwrap();
+        assert_eq!("Post", post["page"]["title"]);
+    }
 }
\ No newline at end of file
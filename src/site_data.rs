@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::front_matter::toml_to_json;
+
+pub const DATA_TOML_FILE_NAME: &str = "_data.toml";
+pub const DATA_JSON_FILE_NAME: &str = "_data.json";
+
+/// Deep-merges the data files of `dirs`, ordered deepest first, so the nearest
+/// file wins. Within a directory, `_data.json` wins over `_data.toml`.
+pub fn load_cascaded_data(dirs: &[&Path]) -> JsonValue {
+    let mut data = JsonValue::Object(Map::new());
+
+    for dir in dirs.iter().rev() {
+        for file_name in [DATA_TOML_FILE_NAME, DATA_JSON_FILE_NAME] {
+            let path = dir.join(file_name);
+
+            if !path.is_file() {
+                continue;
+            }
+
+            match read_data_file(path.as_path()) {
+                Ok(file_data @ JsonValue::Object(_)) => merge(&mut data, file_data),
+                Ok(_) => log::warn!("Ignoring {:?}: data must be a table", path),
+                Err(err) => log::warn!("Ignoring {:?}: {}", path, err),
+            }
+        }
+    }
+
+    data
+}
+
+fn read_data_file(path: &Path) -> Result<JsonValue, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(contents.as_str()).map_err(|err| err.to_string()),
+        _ => toml::from_str(contents.as_str()).map(toml_to_json).map_err(|err| err.to_string()),
+    }
+}
+
+/// Merges tables key by key, replacing anything else.
+fn merge(base: &mut JsonValue, value: JsonValue) {
+    match (base, value) {
+        (JsonValue::Object(base), JsonValue::Object(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (base, value) => *base = value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn cascaded_data_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+
+        fs::write(root.join(DATA_TOML_FILE_NAME), "[site]\nname = \"Site\"\nlang = \"en\"\n\n[nav]\nitems = [\"a\", \"b\"]").unwrap();
+        fs::write(sub.join(DATA_TOML_FILE_NAME), "[site]\nlang = \"fr\"").unwrap();
+        fs::write(sub.join(DATA_JSON_FILE_NAME), "{ \"nav\": { \"items\": [\"c\"] } }").unwrap();
+
+        assert_eq!(
+            json!({ "site": { "name": "Site", "lang": "fr" }, "nav": { "items": ["c"] } }),
+            load_cascaded_data(&[sub.as_path(), root]),
+        );
+
+        assert_eq!(
+            json!({ "site": { "name": "Site", "lang": "en" }, "nav": { "items": ["a", "b"] } }),
+            load_cascaded_data(&[root]),
+        );
+
+        // Invalid files are skipped
+        fs::write(sub.join(DATA_JSON_FILE_NAME), "{ nope").unwrap();
+        assert_eq!("fr", load_cascaded_data(&[sub.as_path(), root])["site"]["lang"]);
+    }
+}
\ No newline at end of file